@@ -1,32 +1,151 @@
 use crate::primitive::Type;
 use crate::token::Token;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum AST {
     Number(i32),
     StrLiteral(String),
+    Ident(String),
+    Bool(bool),
+    Null,
+    NamedArg(String, Box<AST>),
+    // `field: type = default` as passed to `addColumn`.
+    Column(Member, Option<Box<AST>>),
     BinOP(Box<AST>, OP, Box<AST>),
+    // `upper(name)`, a built-in function.
+    Call(String, Vec<AST>),
+    // A placeholder of a prepared statement, `$1` or `:name`.
+    Param(String),
+    // `new.id` or `old.id` inside a trigger.
+    Field(String, String),
+    // `nextval(order_seq)`, replaced by the sequence's next value before evaluation.
+    NextVal(String),
     MethodCall {
         table: String,
         name: String,
         args: Vec<AST>,
     },
+    Chain {
+        receiver: Box<AST>,
+        name: String,
+        args: Vec<AST>,
+    },
     TableDef {
         name: String,
         members: Vec<Member>,
+        // Table-level `check(...)` constraints.
+        checks: Vec<AST>,
+    },
+    // `View Adults = User.select().where(age >= 18)`.
+    ViewDef {
+        name: String,
+        query: Box<AST>,
     },
+    // `View materialized [autoRefresh] Foo = ...`, stored as a real table.
+    Materialized {
+        view: Box<AST>,
+        auto_refresh: bool,
+    },
+    // `trigger audit after insert on User = Log.insert(new.id)`.
+    TriggerDef {
+        table: String,
+        trigger: Box<Trigger>,
+    },
+    // `method User.adults(minAge: int) = select().where(age >= minAge)`.
+    MethodDef {
+        table: String,
+        method: Box<Method>,
+    },
+    // `Table ifNotExists Foo {...}` and `Table replace Foo {...}`.
+    IfNotExists(Box<AST>),
+    Replace(Box<AST>),
+    Drop(String),
+    Tables,
+    // `explain User.select()`: the plan a query would run with.
+    Explain(Box<AST>),
+    // `analyze User` or `analyze` for every table.
+    Analyze(Option<String>),
+    Sequence(String),
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    RollbackTo(String),
+    Release(String),
 }
 
 impl AST {
     pub fn binop(left: Self, op: OP, right: Self) -> Self {
         AST::BinOP(Box::new(left), op, Box::new(right))
     }
+
+    pub fn named_arg(name: String, value: Self) -> Self {
+        AST::NamedArg(name, Box::new(value))
+    }
+
+    pub fn chain(receiver: Self, name: String, args: Vec<Self>) -> Self {
+        AST::Chain {
+            receiver: Box::new(receiver),
+            name,
+            args,
+        }
+    }
+
+    // Rebuilds the tree bottom-up, replacing every node with `f(node)`.
+    pub fn try_map<F>(self, f: &mut F) -> Result<Self, String>
+    where
+        F: FnMut(Self) -> Result<Self, String>,
+    {
+        let tree = match self {
+            AST::NamedArg(name, value) => AST::named_arg(name, value.try_map(f)?),
+            AST::BinOP(left, op, right) => AST::binop(left.try_map(f)?, op, right.try_map(f)?),
+            AST::Call(name, args) => AST::Call(name, Self::try_map_all(args, f)?),
+            AST::MethodCall { table, name, args } => AST::MethodCall {
+                table,
+                name,
+                args: Self::try_map_all(args, f)?,
+            },
+            AST::Chain {
+                receiver,
+                name,
+                args,
+            } => AST::chain(receiver.try_map(f)?, name, Self::try_map_all(args, f)?),
+            AST::Explain(query) => AST::Explain(Box::new(query.try_map(f)?)),
+            tree => tree,
+        };
+        f(tree)
+    }
+
+    fn try_map_all<F>(trees: Vec<Self>, f: &mut F) -> Result<Vec<Self>, String>
+    where
+        F: FnMut(Self) -> Result<Self, String>,
+    {
+        trees.into_iter().map(|tree| tree.try_map(f)).collect()
+    }
+
+    // Reads which return rows instead of mutating the database.
+    pub fn is_query(&self) -> bool {
+        match self {
+            AST::MethodCall { name, .. } => name == "select" || name == "describe",
+            AST::Tables | AST::Explain(_) => true,
+            AST::Chain { receiver, name, .. } => {
+                matches!(&**name, "where" | "orderBy" | "join") && receiver.is_query()
+            }
+            _ => false,
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum OP {
     EqEq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
     Add,
     Minus,
     Mul,
@@ -37,6 +156,11 @@ impl OP {
     pub fn from_token(token: Token) -> Self {
         match token {
             Token::EqEq => OP::EqEq,
+            Token::NotEq => OP::NotEq,
+            Token::Lt => OP::Lt,
+            Token::LtEq => OP::LtEq,
+            Token::Gt => OP::Gt,
+            Token::GtEq => OP::GtEq,
             Token::Add => OP::Add,
             Token::Minus => OP::Minus,
             Token::Mul => OP::Mul,
@@ -44,10 +168,207 @@ impl OP {
             _ => unreachable!(),
         }
     }
+
+    // The comparison with its sides swapped: `3 < x` is `x > 3`.
+    pub fn flip(&self) -> Self {
+        match self {
+            OP::Lt => OP::Gt,
+            OP::LtEq => OP::GtEq,
+            OP::Gt => OP::Lt,
+            OP::GtEq => OP::LtEq,
+            op => op.clone(),
+        }
+    }
+
+    // The comparison true where this one is false: `x < 3` against `x >= 3`.
+    pub fn negate(&self) -> Option<Self> {
+        match self {
+            OP::EqEq => Some(OP::NotEq),
+            OP::NotEq => Some(OP::EqEq),
+            OP::Lt => Some(OP::GtEq),
+            OP::LtEq => Some(OP::Gt),
+            OP::Gt => Some(OP::LtEq),
+            OP::GtEq => Some(OP::Lt),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OP {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            OP::EqEq => "==",
+            OP::NotEq => "!=",
+            OP::Lt => "<",
+            OP::LtEq => "<=",
+            OP::Gt => ">",
+            OP::GtEq => ">=",
+            OP::Add => "+",
+            OP::Minus => "-",
+            OP::Mul => "*",
+            OP::Div => "/",
+        };
+        write!(f, "{}", op)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+// Prints expressions back in source form, parenthesizing nested operations.
+impl fmt::Display for AST {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AST::Number(i) => write!(f, "{}", i),
+            AST::StrLiteral(s) => write!(f, "{:?}", s),
+            AST::Ident(name) => write!(f, "{}", name),
+            AST::Bool(b) => write!(f, "{}", b),
+            AST::Null => write!(f, "null"),
+            AST::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            AST::Field(row, field) => write!(f, "{}.{}", row, field),
+            AST::NextVal(sequence) => write!(f, "nextval({})", sequence),
+            AST::Param(param) => write!(f, "{}", param),
+            AST::NamedArg(name, value) => write!(f, "{}: {}", name, value),
+            AST::BinOP(left, op, right) => {
+                for (i, side) in [left, right].iter().enumerate() {
+                    if i == 1 {
+                        write!(f, " {} ", op)?;
+                    }
+                    match ***side {
+                        AST::BinOP(..) => write!(f, "({})", side)?,
+                        _ => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
+            tree => write!(f, "{:?}", tree),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Member {
     pub typ: Type,
     pub field: String,
+    pub constraints: Vec<Constraint>,
+}
+
+impl Member {
+    pub fn new(field: &str, typ: Type) -> Self {
+        Self {
+            typ,
+            field: field.to_string(),
+            constraints: vec![],
+        }
+    }
+
+    pub fn references(&self) -> Option<(&str, &str, OnDelete)> {
+        self.constraints
+            .iter()
+            .find_map(|constraint| match constraint {
+                Constraint::References {
+                    table,
+                    field,
+                    on_delete,
+                } => Some((&**table, &**field, *on_delete)),
+                _ => None,
+            })
+    }
+
+    // The expressions of the member's `check(...)` constraints.
+    pub fn checks(&self) -> impl Iterator<Item = &AST> {
+        self.constraints
+            .iter()
+            .filter_map(|constraint| match constraint {
+                Constraint::Check(expr) => Some(expr),
+                _ => None,
+            })
+    }
+
+    pub fn is_auto(&self) -> bool {
+        self.constraints.contains(&Constraint::Auto)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum Constraint {
+    // `references User(id)`: values must exist in `User.id`.
+    References {
+        table: String,
+        field: String,
+        on_delete: OnDelete,
+    },
+    // `check(age >= 0)`: rows must make the expression true.
+    Check(AST),
+    // `auto`: generated by the table when an insert leaves it out.
+    Auto,
+}
+
+// A method defined in the language, called like the built-in ones. Its body
+// is a call on the table itself, with parameters used as identifiers.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Method {
+    pub name: String,
+    pub params: Vec<Member>,
+    pub body: AST,
+}
+
+// A statement run for every row written by `event`, with the row's values
+// available as `old.x` and `new.x`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Trigger {
+    pub name: String,
+    pub timing: Timing,
+    pub event: Event,
+    pub action: AST,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum Timing {
+    Before,
+    After,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    Insert,
+    Update,
+    Delete,
+}
+
+// What deleting a referenced row does to the rows referencing it.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum OnDelete {
+    Restrict,
+    Cascade,
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constraint::References {
+                table,
+                field,
+                on_delete,
+            } => {
+                write!(f, "references {}({})", table, field)?;
+                if *on_delete == OnDelete::Cascade {
+                    write!(f, " cascade")?;
+                }
+                Ok(())
+            }
+            Constraint::Check(expr) => write!(f, "check({})", expr),
+            Constraint::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+#[test]
+fn display() {
+    let tree = AST::binop(
+        AST::binop(AST::Ident("age".to_string()), OP::Add, AST::Number(1)),
+        OP::GtEq,
+        AST::StrLiteral("x".to_string()),
+    );
+    assert_eq!(tree.to_string(), "(age + 1) >= \"x\"");
 }
@@ -0,0 +1,122 @@
+// Splits CSV text into records, each with the line it starts on. A quoted
+// field may span lines, so a record ends at the first newline outside quotes.
+pub fn records(src: &str) -> Vec<(usize, String)> {
+    let mut records = vec![];
+    let mut record = String::new();
+    let (mut line, mut start) = (1, 1);
+    let mut quoted = false;
+    for ch in src.chars() {
+        match ch {
+            '\n' if !quoted => {
+                let end = record.strip_suffix('\r').unwrap_or(&record).to_string();
+                records.push((start, end));
+                record.clear();
+                line += 1;
+                start = line;
+                continue;
+            }
+            '\n' => line += 1,
+            // An escaped `""` toggles twice, leaving the field quoted.
+            '"' => quoted = !quoted,
+            _ => {}
+        }
+        record.push(ch);
+    }
+    if !record.is_empty() {
+        records.push((start, record));
+    }
+    records
+}
+
+// Splits one CSV record into fields, unquoting `"..."` and `""` escapes.
+// A field left empty without quotes is `None`, which stands for null.
+pub fn split(line: &str) -> Vec<Option<String>> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    // Whether the current field had quotes, so it is a string even if empty.
+    let mut string = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => {
+                quoted = !quoted;
+                string = true;
+            }
+            (',', false) => fields.push(finish(&mut field, &mut string)),
+            _ => field.push(ch),
+        }
+    }
+    fields.push(finish(&mut field, &mut string));
+    fields
+}
+
+fn finish(field: &mut String, string: &mut bool) -> Option<String> {
+    let field = std::mem::take(field);
+    match std::mem::take(string) {
+        false if field.is_empty() => None,
+        _ => Some(field),
+    }
+}
+
+// Quotes a field when it would otherwise be split or unquoted wrongly, or
+// read back as null.
+pub fn escape(field: &str) -> String {
+    if field.is_empty() || field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn join(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| escape(field))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[test]
+fn split_line() {
+    let some = |field: &str| Some(field.to_string());
+    assert_eq!(split("1,kuru"), vec![some("1"), some("kuru")]);
+    assert_eq!(split("1,,\"\""), vec![some("1"), None, some("")]);
+    assert_eq!(
+        split("\"a, b\",\"say \"\"hi\"\"\""),
+        vec![some("a, b"), some("say \"hi\"")]
+    );
+}
+
+#[test]
+fn split_records() {
+    let records = records("id,s\n1,\"a\nb\"\r\n\n2,\"say \"\"\nhi\"\"\"\n3,c");
+    let record = |line, s: &str| (line, s.to_string());
+    assert_eq!(
+        records,
+        vec![
+            record(1, "id,s"),
+            record(2, "1,\"a\nb\""),
+            record(4, ""),
+            record(5, "2,\"say \"\"\nhi\"\"\""),
+            record(7, "3,c"),
+        ]
+    );
+}
+
+#[test]
+fn join_fields() {
+    let fields = vec![
+        "a, b".to_string(),
+        "say \"hi\"".to_string(),
+        "c".to_string(),
+        String::new(),
+    ];
+    assert_eq!(join(&fields), "\"a, b\",\"say \"\"hi\"\"\",c,\"\"");
+    let split: Vec<String> = split(&join(&fields)).into_iter().flatten().collect();
+    assert_eq!(split, fields);
+}
@@ -1,73 +1,2427 @@
-use crate::{ast::AST, table::Table};
+use crate::{
+    ast::{Event, Member, OnDelete, Timing, AST},
+    function::{Aggregate, Registry, Signature},
+    json, optimize,
+    parser::Parser,
+    plan::{Logical, Physical},
+    primitive::Type,
+    result::ResultSet,
+    statement::Statement,
+    statistics::Statistics,
+    table::{Mark, Table, Value, Values, Version},
+    tokenizer::Tokenizer,
+};
 use std::fs;
 
 use bincode::serialize;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
 
 type Identifier = String;
+type R = Result<(), String>;
 
-#[derive(Serialize, Deserialize, Debug)]
+// A committed version of the database which reads can be pinned to. The row
+// versions it can see are kept until every copy of it has been dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot(Arc<Version>);
+
+// Versions of the snapshots handed out, shared by clones of a database.
+type Pins = Arc<Mutex<Vec<Weak<Version>>>>;
+
+type Tables = BTreeMap<Identifier, Arc<Table>>;
+
+// Triggers may write to tables with triggers of their own, up to this depth.
+const MAX_TRIGGER_DEPTH: usize = 16;
+
+// The query behind a materialized view, whose rows live in a table of the same name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Materialized {
+    query: AST,
+    // Refresh after every statement writing to the table the query reads.
+    auto_refresh: bool,
+}
+
+// How a failing statement puts back a table it changed: the whole table as
+// it was, or missing, once its schema changed, or else where its rows stood.
+#[derive(Debug, Clone)]
+enum Undo {
+    Table(Option<Arc<Table>>),
+    Rows(Mark),
+}
+
+// The schema and rows which `rollback` and `rollback to` go back to.
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    table: Tables,
+    views: BTreeMap<Identifier, AST>,
+    materialized: BTreeMap<Identifier, Materialized>,
+}
+
+// Tables are shared copy-on-write, so cloning a database is cheap and a
+// clone keeps reading the tables as they were.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Database {
-    table: BTreeMap<Identifier, Table>,
+    table: Tables,
+    // Latest committed version. Uncommitted writes are stamped `version + 1`.
+    version: Version,
+    // Last value handed out by each named sequence. Sequences are not
+    // transactional: `rollback` does not give values back.
+    sequences: BTreeMap<Identifier, i32>,
+    // Stored queries which can be read like tables.
+    views: BTreeMap<Identifier, AST>,
+    materialized: BTreeMap<Identifier, Materialized>,
+    // Rust functions registered by the embedding application.
+    #[serde(skip)]
+    functions: Registry,
+    // Tables written by the statement being executed.
+    #[serde(skip)]
+    touched: HashSet<Identifier>,
+    // What undoes the statement being executed, per table it changed.
+    #[serde(skip)]
+    undo: BTreeMap<Identifier, Undo>,
+    // Triggers currently running, one inside the other.
+    #[serde(skip)]
+    firing: usize,
+    // The database as it was at `begin`, restored by `rollback`.
+    #[serde(skip)]
+    snapshot: Option<Checkpoint>,
+    // Named checkpoints taken inside the current transaction, oldest first.
+    #[serde(skip)]
+    savepoints: Vec<(Identifier, Checkpoint)>,
+    // Inside `transaction`, which commits or rolls back by itself.
+    #[serde(skip)]
+    scoped: bool,
+    #[serde(skip)]
+    pins: Pins,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Database {
     pub fn new() -> Self {
         Self {
             table: BTreeMap::new(),
+            version: 0,
+            sequences: BTreeMap::new(),
+            views: BTreeMap::new(),
+            materialized: BTreeMap::new(),
+            functions: Registry::default(),
+            touched: HashSet::new(),
+            undo: BTreeMap::new(),
+            firing: 0,
+            snapshot: None,
+            savepoints: vec![],
+            scoped: false,
+            pins: Pins::default(),
         }
     }
 
-    pub fn execute(&mut self, tree: AST) -> Result<(), &'static str> {
-        match tree {
-            AST::TableDef { name, members } => {
-                self.table.insert(name.clone(), Table::new(name, members));
+    pub fn execute(&mut self, tree: AST) -> Result<(), String> {
+        self.functions.clone().scope(|| self.run(tree))
+    }
+
+    // A statement which fails, or whose triggers fail, is undone as a whole,
+    // inside a transaction or not. Only the tables it changed are put back.
+    fn run(&mut self, tree: AST) -> Result<(), String> {
+        self.touched.clear();
+        self.undo.clear();
+        let (views, materialized) = (self.views.clone(), self.materialized.clone());
+        let result = self.apply(tree).and_then(|_| self.refresh_touched());
+        match result {
+            Ok(_) if self.snapshot.is_none() => self.reclaim(false),
+            Ok(_) => {}
+            Err(_) => {
+                for (name, undo) in std::mem::take(&mut self.undo) {
+                    match (undo, self.table.get_mut(&name)) {
+                        (Undo::Table(Some(table)), _) => {
+                            self.table.insert(name, table);
+                        }
+                        (Undo::Table(None), _) => {
+                            self.table.remove(&name);
+                        }
+                        (Undo::Rows(mark), Some(table)) => Arc::make_mut(table).undo(mark),
+                        (Undo::Rows(_), None) => {}
+                    }
+                }
+                self.views = views;
+                self.materialized = materialized;
+            }
+        }
+        result
+    }
+
+    fn apply(&mut self, tree: AST) -> Result<(), String> {
+        match self.nextval(tree)? {
+            AST::TableDef {
+                name,
+                members,
+                checks,
+            } => {
+                if self.table.contains_key(&name) || self.views.contains_key(&name) {
+                    return Err("Table already exists!!".to_string());
+                }
+                let table = self.define(name.clone(), members, checks)?;
+                self.keep(&name);
+                self.table.insert(name, Arc::new(table));
+                Ok(())
+            }
+            AST::IfNotExists(def) => match *def {
+                AST::TableDef { ref name, .. } if self.table.contains_key(name) => Ok(()),
+                def => self.apply(def),
+            },
+            // The old table is only dropped once the new one is known to be valid.
+            AST::Replace(def) => match *def {
+                AST::TableDef {
+                    name,
+                    members,
+                    checks,
+                } => {
+                    if self.views.contains_key(&name) {
+                        return Err("Table already exists!!".to_string());
+                    }
+                    // Rows of other tables would be left referencing nothing.
+                    if let Some((child, _)) = self
+                        .referencing(&name)
+                        .iter()
+                        .find(|(child, _)| *child != name)
+                    {
+                        return Err(format!("Table is referenced by {}!!", child));
+                    }
+                    let table = self.define(name.clone(), members, checks)?;
+                    self.keep(&name);
+                    self.table.insert(name, Arc::new(table));
+                    Ok(())
+                }
+                def => self.apply(def),
+            },
+            AST::Drop(name) => {
+                // Views reading it would be left reading nothing.
+                if let Some(view) = self.readers(&name).first() {
+                    return Err(format!("Table is read by view {}!!", view));
+                }
+                if self.views.remove(&name).is_some() {
+                    return Ok(());
+                }
+                self.materialized.remove(&name);
+                if let Some((child, _)) = self
+                    .referencing(&name)
+                    .iter()
+                    .find(|(child, _)| *child != name)
+                {
+                    return Err(format!("Table is referenced by {}!!", child));
+                }
+                self.keep(&name);
+                match self.table.remove(&name) {
+                    Some(_) => Ok(()),
+                    None => Err("Table not found!!".to_string()),
+                }
+            }
+            AST::ViewDef { name, query } => {
+                if self.table.contains_key(&name) || self.views.contains_key(&name) {
+                    return Err("Table already exists!!".to_string());
+                }
+                self.read(self.version, (*query).clone())?;
+                self.views.insert(name, *query);
+                Ok(())
+            }
+            AST::Materialized { view, auto_refresh } => match *view {
+                AST::ViewDef { name, query } => {
+                    if self.table.contains_key(&name) || self.views.contains_key(&name) {
+                        return Err("Table already exists!!".to_string());
+                    }
+                    let query = *query;
+                    self.materialized.insert(
+                        name.clone(),
+                        Materialized {
+                            query,
+                            auto_refresh,
+                        },
+                    );
+                    self.refresh(&name).inspect_err(|_| {
+                        self.materialized.remove(&name);
+                    })
+                }
+                _ => Err("Unimplemented AST!!".to_string()),
+            },
+            AST::MethodDef { table, method } => self.alter(&table)?.add_method(*method),
+            AST::TriggerDef { table, trigger } => self.alter(&table)?.add_trigger(*trigger),
+            AST::Analyze(name) => {
+                let names = match name {
+                    Some(name) => vec![name],
+                    None => self
+                        .table
+                        .keys()
+                        .filter(|name| !self.materialized.contains_key(*name))
+                        .cloned()
+                        .collect(),
+                };
+                for name in names {
+                    let version = self.version + 1;
+                    self.alter(&name)?.analyze(version)?;
+                }
+                Ok(())
+            }
+            AST::Sequence(name) => {
+                if self.sequences.contains_key(&name) {
+                    return Err("Sequence already exists!!".to_string());
+                }
+                self.sequences.insert(name, 0);
+                Ok(())
+            }
+            AST::MethodCall { table, name, args } if name == "renameTo" => match args.as_slice() {
+                [AST::Ident(to)] => self.rename_table(&table, to),
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            AST::MethodCall { table, name, args } if name == "refresh" && args.is_empty() => {
+                self.refresh(&table)?;
+                self.autocommit();
+                Ok(())
+            }
+            AST::MethodCall { table, name, args } if name == "insert" => {
+                self.insert(&table, args)?;
+                self.autocommit();
+                Ok(())
+            }
+            AST::MethodCall { table, name, args }
+                if name == "importCsv" || name == "importNdjson" =>
+            {
+                let rows = self.get(&table)?.imported(&name, &args)?;
+                for row in rows {
+                    self.insert_row(&table, row)?;
+                }
+                self.autocommit();
+                Ok(())
+            }
+            AST::MethodCall { table, name, args }
+                if name == "dropColumn" || name == "renameColumn" =>
+            {
+                self.alter_column(&table, name, args)?;
+                self.autocommit();
+                Ok(())
+            }
+            AST::MethodCall { table, name, args } if name == "delete" => {
+                let version = self.version + 1;
+                let positions = self.get(&table)?.addressed(args, version);
+                self.delete(&table, positions.into_iter().collect())?;
+                self.autocommit();
+                Ok(())
+            }
+            AST::MethodCall { table, name, args } if name == "update" => {
+                let positions = self.get(&table)?.find(&[], self.version + 1)?;
+                self.update(&table, positions, &args)?;
+                self.autocommit();
                 Ok(())
             }
+            AST::MethodCall { table, name, args } if self.has_method(&table, &name) => {
+                let body = self.get(&table)?.call(&name, args)?;
+                if self.is_query(&body) {
+                    return Err("Method returns rows, use query!!".to_string());
+                }
+                self.apply(body)
+            }
             AST::MethodCall { table, name, args } => {
-                if let Some(table) = self.table.get_mut(&table) {
-                    table.execute(name, args)
-                } else {
-                    Err("Table not found!!")
+                let version = self.version + 1;
+                self.alter(&table)?.execute(name, args, version)?;
+                self.autocommit();
+                Ok(())
+            }
+            AST::Chain {
+                receiver,
+                name,
+                args,
+            } if name == "update" || name == "delete" => {
+                let (table, filters) = match Self::filters(*receiver)? {
+                    (AST::MethodCall { table, name, args }, filters)
+                        if name == "select" && args.is_empty() =>
+                    {
+                        (table, filters)
+                    }
+                    _ => return Err("Not a query!!".to_string()),
+                };
+                let positions = self.get(&table)?.find(&filters, self.version + 1)?;
+                match &*name {
+                    "update" => self.update(&table, positions, &args)?,
+                    _ => self.delete(&table, positions)?,
+                }
+                self.autocommit();
+                Ok(())
+            }
+            AST::Chain {
+                receiver,
+                name,
+                args,
+            } if name == "exportJson" || name == "exportNdjson" => match args.as_slice() {
+                [AST::StrLiteral(path)] => {
+                    let (members, rows) = self.read(self.version + 1, *receiver)?;
+                    json::write(path, &name, &members, &rows)
+                }
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            AST::Begin => {
+                if self.snapshot.is_some() {
+                    return Err("Transaction already started!!".to_string());
+                }
+                self.snapshot = Some(self.checkpoint());
+                Ok(())
+            }
+            AST::Commit | AST::Rollback if self.scoped => {
+                Err("Transaction is ended by Database::transaction!!".to_string())
+            }
+            AST::Commit => match self.snapshot.take() {
+                Some(_) => {
+                    self.version += 1;
+                    self.savepoints.clear();
+                    Ok(())
+                }
+                None => Err("No transaction!!".to_string()),
+            },
+            AST::Rollback => match self.snapshot.take() {
+                Some(checkpoint) => {
+                    self.restore(checkpoint);
+                    self.savepoints.clear();
+                    Ok(())
                 }
+                None => Err("No transaction!!".to_string()),
+            },
+            AST::Savepoint(name) => {
+                if self.snapshot.is_none() {
+                    return Err("No transaction!!".to_string());
+                }
+                self.savepoints.push((name, self.checkpoint()));
+                Ok(())
+            }
+            AST::RollbackTo(name) => {
+                let index = self.savepoint(&name)?;
+                // The savepoint itself survives, so it can be rolled back to again.
+                self.savepoints.truncate(index + 1);
+                self.restore(self.savepoints[index].1.clone());
+                Ok(())
             }
-            _ => Err("Unimplemented AST!!"),
+            AST::Release(name) => {
+                let index = self.savepoint(&name)?;
+                self.savepoints.truncate(index);
+                Ok(())
+            }
+            _ => Err("Unimplemented AST!!".to_string()),
         }
     }
 
-    pub fn save(&self) {
-        let mut encoded: Vec<u8> = serialize(&self).unwrap();
-        let mut f = BufWriter::new(fs::File::create("db.dump").unwrap());
-        f.write_all(&mut encoded).unwrap();
+    // A new, empty table as defined by `Table name {...}`.
+    fn define(
+        &self,
+        name: Identifier,
+        members: Vec<Member>,
+        checks: Vec<AST>,
+    ) -> Result<Table, String> {
+        if members
+            .iter()
+            .any(|member| member.is_auto() && member.typ != Type::Int)
+        {
+            return Err("Auto column must be int!!".to_string());
+        }
+        for (parent, field, _) in members.iter().filter_map(Member::references) {
+            let exists = match self.table.get(parent) {
+                _ if parent == name => members.iter().any(|m| m.field == field),
+                Some(table) => table.position(field).is_some(),
+                None => false,
+            };
+            if !exists {
+                return Err(format!(
+                    "Referenced column {}({}) not found!!",
+                    parent, field
+                ));
+            }
+        }
+        Ok(Table::new(name, members).with_checks(checks))
     }
-}
 
-#[test]
-fn new() {
-    let db = Database::new();
-    assert_eq!(db.table.len(), 0);
-}
+    fn get(&self, name: &str) -> Result<&Table, String> {
+        match self.table.get(name) {
+            Some(table) => Ok(table),
+            None => Err("Table not found!!".to_string()),
+        }
+    }
 
-#[test]
-fn execute() {
-    let mut db = Database::new();
-    // Create new table
-    assert_eq!(
-        db.execute(AST::TableDef {
-            name: "Hoge".to_string(),
-            members: vec![]
-        }),
+    // A table to write rows to. The first write of a statement marks where
+    // its rows stood.
+    fn get_mut(&mut self, name: &str) -> Result<&mut Table, String> {
+        if self.materialized.contains_key(name) {
+            return Err("Materialized view is read-only!!".to_string());
+        }
+        self.touched.insert(name.to_string());
+        let table = match self.table.get_mut(name) {
+            Some(table) => Arc::make_mut(table),
+            None => return Err("Table not found!!".to_string()),
+        };
+        if !self.undo.contains_key(name) {
+            self.undo.insert(name.to_string(), Undo::Rows(table.mark()));
+        }
+        Ok(table)
+    }
+
+    // A table whose schema is about to change, saved whole first.
+    fn alter(&mut self, name: &str) -> Result<&mut Table, String> {
+        if self.materialized.contains_key(name) {
+            return Err("Materialized view is read-only!!".to_string());
+        }
+        self.keep(name);
+        self.touched.insert(name.to_string());
+        match self.table.get_mut(name) {
+            Some(table) => Ok(Arc::make_mut(table)),
+            None => Err("Table not found!!".to_string()),
+        }
+    }
+
+    // Keeps table `name`, or its absence, as it was before the statement,
+    // unless an earlier change of the statement already did.
+    fn keep(&mut self, name: &str) {
+        let saved = match self.undo.get(name) {
+            Some(Undo::Table(_)) => return,
+            Some(Undo::Rows(mark)) => {
+                let mut table = self.table[name].clone();
+                Arc::make_mut(&mut table).undo(mark.clone());
+                Some(table)
+            }
+            None => self.table.get(name).cloned(),
+        };
+        self.undo.insert(name.to_string(), Undo::Table(saved));
+    }
+
+    // Replaces every `nextval(seq)` in `tree` with the next value of `seq`,
+    // once per statement.
+    fn nextval(&mut self, tree: AST) -> Result<AST, String> {
+        let sequences = &mut self.sequences;
+        tree.try_map(&mut |node| match node {
+            AST::NextVal(name) => match sequences.get_mut(&name) {
+                Some(value) => {
+                    *value += 1;
+                    Ok(AST::Number(*value))
+                }
+                None => Err("Sequence not found!!".to_string()),
+            },
+            node => Ok(node),
+        })
+    }
+
+    // Runs the triggers of `name` for one row, with `old` and `new` bound to
+    // its values before and after the write.
+    fn fire(
+        &mut self,
+        name: &str,
+        timing: Timing,
+        event: Event,
+        old: Option<&Values>,
+        new: Option<&Values>,
+    ) -> R {
+        let table = self.get(name)?;
+        let mut actions = vec![];
+        for trigger in table.triggers() {
+            if trigger.timing != timing || trigger.event != event {
+                continue;
+            }
+            let action = trigger.action.clone().try_map(&mut |node| match node {
+                AST::Field(row, field) => {
+                    let values = match &*row {
+                        "old" => old,
+                        "new" => new,
+                        _ => return Err(format!("Unknown row `{}`!!", row)),
+                    };
+                    let position = table.position(&field);
+                    match (values, position) {
+                        (Some(values), Some(position)) => Ok(values[position].clone().into()),
+                        (None, _) => Err(format!("No {} row in this trigger!!", row)),
+                        (_, None) => Err("Unknown column!!".to_string()),
+                    }
+                }
+                node => Ok(node),
+            })?;
+            actions.push(action);
+        }
+        if actions.is_empty() {
+            return Ok(());
+        }
+        if self.firing >= MAX_TRIGGER_DEPTH {
+            return Err("Triggers nested too deeply!!".to_string());
+        }
+        self.firing += 1;
+        let result = actions
+            .into_iter()
+            .try_for_each(|action| self.apply(action));
+        self.firing -= 1;
+        result
+    }
+
+    // Outside a transaction every statement commits on its own, once any
+    // triggers it fires are done.
+    fn autocommit(&mut self) {
+        if self.snapshot.is_none() && self.firing == 0 {
+            self.version += 1;
+        }
+    }
+
+    // The oldest version anyone can still read: the latest one, or that of
+    // the oldest snapshot still alive.
+    fn horizon(&self) -> Version {
+        let mut pins = self.pins.lock().unwrap_or_else(PoisonError::into_inner);
+        pins.retain(|pin| pin.strong_count() > 0);
+        pins.iter()
+            .filter_map(|pin| pin.upgrade())
+            .map(|version| *version)
+            .fold(self.version, Version::min)
+    }
+
+    // Drops the row versions no reader can see any more, from every table
+    // or only those where enough have piled up since they were last dropped.
+    fn reclaim(&mut self, all: bool) {
+        let horizon = self.horizon();
+        for table in self.table.values_mut() {
+            if (all && table.reclaimable(horizon)) || (!all && table.needs_vacuum()) {
+                Arc::make_mut(table).vacuum(horizon);
+            }
+        }
+    }
+
+    pub fn vacuum(&mut self) {
+        self.reclaim(true);
+    }
+
+    fn insert(&mut self, name: &str, args: Vec<AST>) -> R {
+        let row = self.get(name)?.row(args)?;
+        self.insert_row(name, row)
+    }
+
+    fn insert_row(&mut self, name: &str, row: Values) -> R {
+        let version = self.version + 1;
+        self.check_references(name, &row, version)?;
+        self.fire(name, Timing::Before, Event::Insert, None, Some(&row))?;
+        self.get_mut(name)?.push(row.clone(), version);
+        self.fire(name, Timing::After, Event::Insert, None, Some(&row))
+    }
+
+    fn update(&mut self, name: &str, positions: Vec<usize>, assignments: &[AST]) -> R {
+        let version = self.version + 1;
+        let table = self.get(name)?;
+        let mut rows = vec![];
+        for position in positions {
+            let values = table.assign(position, assignments)?;
+            self.check_references(name, &values, version)?;
+            self.check_referenced(name, table.values(position), &values, version)?;
+            rows.push((position, values));
+        }
+        for (position, values) in &rows {
+            let old = self.get(name)?.values(*position).clone();
+            self.fire(
+                name,
+                Timing::Before,
+                Event::Update,
+                Some(&old),
+                Some(values),
+            )?;
+            self.get_mut(name)?
+                .replace(*position, values.clone(), version);
+            self.fire(name, Timing::After, Event::Update, Some(&old), Some(values))?;
+        }
         Ok(())
-    );
+    }
 
-    assert_eq!(
-        db.execute(AST::MethodCall {
-            table: "Fuga".to_string(),
-            name: "insert".to_string(),
-            args: vec![]
-        }),
-        Err("Table not found!!")
+    // Deletes the rows at `positions` together with every row reaching them
+    // through `cascade`, or nothing if a `restrict` reference is hit.
+    fn delete(&mut self, name: &str, positions: Vec<usize>) -> R {
+        let version = self.version + 1;
+        let mut plan: Vec<(Identifier, usize)> = positions
+            .into_iter()
+            .map(|position| (name.to_string(), position))
+            .collect();
+        let mut planned: HashSet<_> = plan.iter().cloned().collect();
+        let mut i = 0;
+        while i < plan.len() {
+            let (parent, position) = plan[i].clone();
+            let table = self.get(&parent)?;
+            for (child, member) in self.referencing(&parent) {
+                let (_, field, on_delete) = member.references().unwrap();
+                let key = &table.values(position)[table
+                    .position(field)
+                    .ok_or_else(|| "Unknown column.".to_string())?];
+                for row in self.get(&child)?.lookup(&member.field, key, version)? {
+                    if planned.contains(&(child.clone(), row)) {
+                        continue;
+                    }
+                    if on_delete == OnDelete::Restrict {
+                        return Err(Self::violation(&parent, field, key, &child, &member.field));
+                    }
+                    planned.insert((child.clone(), row));
+                    plan.push((child.clone(), row));
+                }
+            }
+            i += 1;
+        }
+        for (table, position) in plan {
+            let old = self.get(&table)?.values(position).clone();
+            self.fire(&table, Timing::Before, Event::Delete, Some(&old), None)?;
+            self.get_mut(&table)?.remove(position, version);
+            self.fire(&table, Timing::After, Event::Delete, Some(&old), None)?;
+        }
+        Ok(())
+    }
+
+    // Every member of any table referencing `parent`, with its table name.
+    fn referencing(&self, parent: &str) -> Vec<(Identifier, Member)> {
+        let mut members = vec![];
+        for (name, table) in self.table.iter() {
+            for member in table.members() {
+                if let Some((table, _, _)) = member.references() {
+                    if table == parent {
+                        members.push((name.clone(), member.clone()));
+                    }
+                }
+            }
+        }
+        members
+    }
+
+    // Values of `row` in referencing members have to exist in the parent.
+    fn check_references(&self, name: &str, row: &[Value], version: Version) -> R {
+        let members = self.get(name)?.members();
+        for (member, value) in members.iter().zip(row) {
+            if let Some((parent, field, _)) = member.references() {
+                if self.get(parent)?.lookup(field, value, version)?.is_empty() {
+                    return Err(format!(
+                        "Foreign key violation: {}.{} = {} is not in {}({}).",
+                        name, member.field, value, parent, field
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Referenced keys of `old` may only change while nothing references them.
+    fn check_referenced(&self, name: &str, old: &[Value], new: &[Value], version: Version) -> R {
+        let table = self.get(name)?;
+        for (child, member) in self.referencing(name) {
+            let (_, field, _) = member.references().unwrap();
+            let position = table
+                .position(field)
+                .ok_or_else(|| "Unknown column.".to_string())?;
+            let key = &old[position];
+            if &new[position] == key {
+                continue;
+            }
+            if !self
+                .get(&child)?
+                .lookup(&member.field, key, version)?
+                .is_empty()
+            {
+                return Err(Self::violation(name, field, key, &child, &member.field));
+            }
+        }
+        Ok(())
+    }
+
+    fn violation(parent: &str, field: &str, key: &Value, child: &str, member: &str) -> String {
+        format!(
+            "Foreign key violation: {}({}) = {} is referenced by {}.{}.",
+            parent, field, key, child, member
+        )
+    }
+
+    fn rename_table(&mut self, from: &str, to: &str) -> Result<(), String> {
+        if self.table.contains_key(to) || self.views.contains_key(to) {
+            return Err("Table already exists!!".to_string());
+        }
+        if self.materialized.contains_key(from) {
+            return Err("Materialized view is read-only!!".to_string());
+        }
+        self.keep(from);
+        self.keep(to);
+        let mut table = self
+            .table
+            .remove(from)
+            .ok_or_else(|| "Table not found!!".to_string())?;
+        Arc::make_mut(&mut table).rename(to.to_string());
+        self.table.insert(to.to_string(), table);
+        for (child, _) in self.referencing(from) {
+            self.alter(&child)?.rename_referenced_table(from, to);
+        }
+        Ok(())
+    }
+
+    // Drops or renames a column, refusing to drop one which other rows
+    // reference and following a renamed one in the references to it.
+    fn alter_column(&mut self, name: &str, method: Identifier, args: Vec<AST>) -> R {
+        let referenced = |field: &str| {
+            self.referencing(name)
+                .into_iter()
+                .filter(|(_, member)| member.references().is_some_and(|(_, f, _)| f == field))
+                .map(|(child, _)| child)
+                .collect::<Vec<_>>()
+        };
+        let children = match args.first() {
+            Some(AST::Ident(field)) => referenced(field),
+            _ => vec![],
+        };
+        if let (Some(child), "dropColumn") = (children.first(), &*method) {
+            return Err(format!("Column is referenced by {}!!", child));
+        }
+        let version = self.version + 1;
+        self.alter(name)?.execute(method, args.clone(), version)?;
+        if let [AST::Ident(from), AST::Ident(to)] = args.as_slice() {
+            for child in children {
+                self.alter(&child)?.rename_referenced_column(name, from, to);
+            }
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            table: self.table.clone(),
+            views: self.views.clone(),
+            materialized: self.materialized.clone(),
+        }
+    }
+
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.table = checkpoint.table;
+        self.views = checkpoint.views;
+        self.materialized = checkpoint.materialized;
+    }
+
+    fn savepoint(&self, name: &str) -> Result<usize, String> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| "Savepoint not found!!".to_string())
+    }
+
+    // Runs `f` inside a transaction, committing if it succeeds and rolling
+    // every table back if it fails. `f` can't commit or roll back itself.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut Self) -> Result<T, String>,
+    {
+        self.apply(AST::Begin)?;
+        self.scoped = true;
+        let result = f(self);
+        self.scoped = false;
+        match result {
+            Ok(value) => {
+                self.apply(AST::Commit)?;
+                self.reclaim(false);
+                Ok(value)
+            }
+            Err(msg) => {
+                self.apply(AST::Rollback)?;
+                Err(msg)
+            }
+        }
+    }
+
+    // Latest committed version.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    // Takes tables `names` from `copy`, a copy of this database at version
+    // `since` which wrote no other table, with the versions it committed.
+    pub fn merge(&mut self, copy: Database, names: &BTreeSet<Identifier>, since: Version) {
+        for name in names {
+            match copy.table.get(name) {
+                Some(table) => self.table.insert(name.clone(), table.clone()),
+                None => self.table.remove(name),
+            };
+        }
+        self.version += copy.version - since;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let version = Arc::new(self.version);
+        let mut pins = self.pins.lock().unwrap_or_else(PoisonError::into_inner);
+        pins.push(Arc::downgrade(&version));
+        Snapshot(version)
+    }
+
+    // Reads see the current transaction's own writes.
+    pub fn query(&self, tree: AST) -> Result<ResultSet, String> {
+        self.result(self.version + 1, tree)
+    }
+
+    // Reads only what was committed when `snapshot` was taken.
+    pub fn query_at(&self, snapshot: &Snapshot, tree: AST) -> Result<ResultSet, String> {
+        self.result(*snapshot.0, tree)
+    }
+
+    fn result(&self, version: Version, tree: AST) -> Result<ResultSet, String> {
+        let (columns, rows) = self.functions.scope(|| self.read(version, tree))?;
+        Ok(ResultSet::new(columns, rows))
+    }
+
+    // Makes `f` callable in expressions with arguments of the declared types.
+    pub fn register_function<F>(&mut self, name: &str, signature: Signature, f: F) -> R
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.functions.scalar(name, signature, f)
+    }
+
+    // Makes `aggregate` selectable, e.g. `User.select(oldest: max_age(age))`.
+    pub fn register_aggregate<A>(&mut self, name: &str, signature: Signature, aggregate: A) -> R
+    where
+        A: Aggregate + 'static,
+    {
+        self.functions.aggregate(name, signature, aggregate)
+    }
+
+    // Parses `src` once, to be run with values bound to its placeholders.
+    pub fn prepare(&self, src: &str) -> Result<Statement, String> {
+        let tree = Parser::new(Tokenizer::new(src).lex_all()?).parse()?;
+        Statement::new(tree, self)
+    }
+
+    // Runs a query, returning the members describing each column with the rows.
+    fn read(&self, version: Version, tree: AST) -> Result<(Vec<Member>, Vec<Values>), String> {
+        if Logical::selects(&tree) {
+            return self.plan(tree)?.execute(self, version);
+        }
+        if let AST::Explain(query) = tree {
+            let plan = self.plan(*query)?.to_string();
+            let rows = plan
+                .lines()
+                .map(|line| vec![Value::StrLiteral(line.to_string())])
+                .collect();
+            return Ok((vec![Member::new("plan", Type::StrLiteral)], rows));
+        }
+        let (tree, filters) = Self::filters(tree)?;
+        match tree {
+            AST::MethodCall { table, name, args } if name == "describe" && args.is_empty() => {
+                let relation = self.relation(&table, version)?;
+                let rows = relation.members().iter().map(Self::describe).collect();
+                let columns = vec![
+                    Member::new("field", Type::StrLiteral),
+                    Member::new("type", Type::StrLiteral),
+                    Member::new("constraints", Type::StrLiteral),
+                ];
+                Ok((columns, rows))
+            }
+            AST::Tables => {
+                let rows = self
+                    .tables()
+                    .into_iter()
+                    .map(|name| vec![Value::StrLiteral(name.to_string())])
+                    .collect();
+                Ok((vec![Member::new("name", Type::StrLiteral)], rows))
+            }
+            AST::MethodCall { table, name, args } if self.has_method(&table, &name) => {
+                let body = self.get(&table)?.call(&name, args)?;
+                let (members, rows) = self.read(version, body)?;
+                let rows =
+                    Table::from_rows(table, members.clone(), rows).select(&filters, version)?;
+                Ok((members, rows))
+            }
+            _ => Err("Not a query!!".to_string()),
+        }
+    }
+
+    fn plan(&self, query: AST) -> Result<Physical, String> {
+        if !Logical::selects(&query) {
+            return Err("Not a query!!".to_string());
+        }
+        Ok(Physical::plan(Logical::build(query)?, self))
+    }
+
+    // A table, view or system table by name, as of `version`.
+    pub fn relation(&self, name: &str, version: Version) -> Result<Cow<'_, Table>, String> {
+        if let Some(table) = self.table.get(name) {
+            return Ok(Cow::Borrowed(&**table));
+        }
+        match self.view(name, version)? {
+            Some(view) => Ok(Cow::Owned(view)),
+            None => self
+                .catalog(name)
+                .map(Cow::Owned)
+                .ok_or_else(|| "Table not found!!".to_string()),
+        }
+    }
+
+    pub fn indexed(&self, table: &str, field: &str) -> bool {
+        self.table
+            .get(table)
+            .is_some_and(|table| table.indexed(field))
+    }
+
+    pub fn statistics(&self, table: &str) -> Option<&Statistics> {
+        self.table.get(table).and_then(|table| table.statistics())
+    }
+
+    fn has_method(&self, table: &str, name: &str) -> bool {
+        self.table
+            .get(table)
+            .is_some_and(|table| table.method(name).is_some())
+    }
+
+    // Like `AST::is_query`, but also knows which user methods return rows.
+    pub fn is_query(&self, tree: &AST) -> bool {
+        match tree {
+            AST::MethodCall { table, name, .. } => match self.table.get(table) {
+                Some(table) => match table.method(name) {
+                    Some(method) => self.is_query(&method.body),
+                    None => tree.is_query(),
+                },
+                None => tree.is_query(),
+            },
+            tree => tree.is_query(),
+        }
+    }
+
+    // Recomputes the rows of materialized view `name` from its query.
+    fn refresh(&mut self, name: &str) -> R {
+        let query = match self.materialized.get(name) {
+            Some(view) => view.query.clone(),
+            None => return Err("Materialized view not found!!".to_string()),
+        };
+        let (members, rows) = self.read(self.version + 1, query)?;
+        let table = Table::from_rows(name.to_string(), members, rows);
+        self.keep(name);
+        self.table.insert(name.to_string(), Arc::new(table));
+        Ok(())
+    }
+
+    // Refreshes the auto-refreshed views reading a table the last statement
+    // wrote to, including views over those views.
+    fn refresh_touched(&mut self) -> R {
+        let mut touched = std::mem::take(&mut self.touched);
+        loop {
+            let stale = self.materialized.iter().find(|(name, view)| {
+                view.auto_refresh
+                    && !touched.contains(*name)
+                    && self
+                        .source(&view.query)
+                        .is_some_and(|source| touched.contains(source))
+            });
+            match stale.map(|(name, _)| name.clone()) {
+                Some(name) => {
+                    self.refresh(&name)?;
+                    touched.insert(name);
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // The views and materialized views whose query reads `name` directly.
+    fn readers(&self, name: &str) -> Vec<Identifier> {
+        let materialized = self
+            .materialized
+            .iter()
+            .map(|(view, materialized)| (view, &materialized.query));
+        self.views
+            .iter()
+            .chain(materialized)
+            .filter(|(view, query)| *view != name && relations(query).contains(&name))
+            .map(|(view, _)| view.clone())
+            .collect()
+    }
+
+    // The tables a statement writing rows may change, with those its key
+    // checks and view refreshes read, or `None` for any other statement.
+    pub fn footprint(&self, tree: &AST) -> Option<BTreeSet<Identifier>> {
+        let mut pending = vec![Self::written(tree)?];
+        let (mut tables, mut read) = (BTreeSet::new(), BTreeSet::new());
+        while let Some(name) = pending.pop() {
+            if !tables.insert(name.clone()) {
+                continue;
+            }
+            let table = self.table.get(&name)?;
+            for trigger in table.triggers() {
+                pending.push(Self::written(&trigger.action)?);
+            }
+            for member in table.members() {
+                if let Some((parent, _, _)) = member.references() {
+                    pending.push(parent.to_string());
+                }
+            }
+            pending.extend(self.referencing(&name).into_iter().map(|(child, _)| child));
+            for (view, materialized) in &self.materialized {
+                if materialized.auto_refresh && self.source(&materialized.query) == Some(&*name) {
+                    pending.push(view.clone());
+                    read.extend(relations(&materialized.query).into_iter().map(String::from));
+                }
+            }
+        }
+        tables.extend(read);
+        Some(tables)
+    }
+
+    // The table a row write takes its rows from, unless it takes sequence
+    // values, which other writers share.
+    fn written(tree: &AST) -> Option<Identifier> {
+        let mut nextval = false;
+        tree.clone()
+            .try_map(&mut |node| {
+                nextval |= matches!(node, AST::NextVal(_));
+                Ok(node)
+            })
+            .ok()?;
+        match tree {
+            _ if nextval => None,
+            AST::MethodCall { table, name, .. }
+                if ["insert", "update", "delete", "importCsv", "importNdjson"]
+                    .contains(&&**name) =>
+            {
+                Some(table.clone())
+            }
+            AST::Chain { receiver, name, .. } if name == "update" || name == "delete" => {
+                match Self::filters((**receiver).clone()).ok()? {
+                    (AST::MethodCall { table, name, args }, _)
+                        if name == "select" && args.is_empty() =>
+                    {
+                        Some(table)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    // The table a stored query reads from, looking through plain views.
+    fn source<'a>(&'a self, query: &'a AST) -> Option<&'a str> {
+        match query {
+            AST::Chain { receiver, .. } => self.source(receiver),
+            AST::MethodCall { table, .. } => match self.views.get(table) {
+                Some(view) => self.source(view),
+                None => Some(table),
+            },
+            _ => None,
+        }
+    }
+
+    // The rows of view `name` as of `version`, computed from its query.
+    fn view(&self, name: &str, version: Version) -> Result<Option<Table>, String> {
+        match self.views.get(name) {
+            Some(query) => {
+                let (members, rows) = self.read(version, query.clone())?;
+                Ok(Some(Table::from_rows(name.to_string(), members, rows)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Splits `User.select().where(a).where(b)` into its call and filters.
+    fn filters(tree: AST) -> Result<(AST, Vec<AST>), String> {
+        let mut filters = vec![];
+        let mut tree = tree;
+        while let AST::Chain {
+            receiver,
+            name,
+            args,
+        } = tree
+        {
+            match &*name {
+                "where" => filters.extend(args),
+                _ => return Err("Unknown method!!".to_string()),
+            }
+            tree = *receiver;
+        }
+        Ok((tree, optimize::predicates(filters)))
+    }
+
+    // Names of the tables and views.
+    pub fn tables(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .table
+            .keys()
+            .chain(self.views.keys())
+            .map(|name| &**name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    pub fn schema(&self, name: &str) -> Option<&[Member]> {
+        self.table.get(name).map(|table| table.members())
+    }
+
+    fn describe(member: &Member) -> Values {
+        let constraints: Vec<String> = member.constraints.iter().map(|c| c.to_string()).collect();
+        vec![
+            Value::StrLiteral(member.field.clone()),
+            Value::StrLiteral(member.typ.to_string()),
+            Value::StrLiteral(constraints.join(" ")),
+        ]
+    }
+
+    fn bound(value: &Option<Value>) -> Value {
+        match value {
+            Some(value) => Value::StrLiteral(value.to_string()),
+            None => Value::Null,
+        }
+    }
+
+    // System tables describing the schema, queryable like any other table.
+    fn catalog(&self, name: &str) -> Option<Table> {
+        match name {
+            "SysTables" => {
+                let rows = self
+                    .tables()
+                    .into_iter()
+                    .map(|name| vec![Value::StrLiteral(name.to_string())])
+                    .collect();
+                let members = vec![Member::new("name", Type::StrLiteral)];
+                Some(Table::from_rows(name.to_string(), members, rows))
+            }
+            "SysColumns" => {
+                let mut rows = vec![];
+                for (table, members) in self.table.iter().map(|(k, t)| (k, t.members())) {
+                    for member in members {
+                        let mut row = vec![Value::StrLiteral(table.clone())];
+                        row.extend(Self::describe(member));
+                        rows.push(row);
+                    }
+                }
+                let members = vec![
+                    Member::new("table", Type::StrLiteral),
+                    Member::new("field", Type::StrLiteral),
+                    Member::new("type", Type::StrLiteral),
+                    Member::new("constraints", Type::StrLiteral),
+                ];
+                Some(Table::from_rows(name.to_string(), members, rows))
+            }
+            "SysStatistics" => {
+                let mut rows = vec![];
+                for (table, statistics) in self
+                    .table
+                    .iter()
+                    .filter_map(|(k, t)| Some((k, t.statistics()?)))
+                {
+                    for (field, column) in &statistics.columns {
+                        rows.push(vec![
+                            Value::StrLiteral(table.clone()),
+                            Value::StrLiteral(field.clone()),
+                            Value::Int(statistics.rows as i32),
+                            Value::Int(column.distinct as i32),
+                            Value::Int(column.nulls as i32),
+                            Self::bound(&column.min),
+                            Self::bound(&column.max),
+                        ]);
+                    }
+                }
+                let members = vec![
+                    Member::new("table", Type::StrLiteral),
+                    Member::new("field", Type::StrLiteral),
+                    Member::new("rows", Type::Int),
+                    Member::new("distinct", Type::Int),
+                    Member::new("nulls", Type::Int),
+                    Member::new("min", Type::StrLiteral),
+                    Member::new("max", Type::StrLiteral),
+                ];
+                Some(Table::from_rows(name.to_string(), members, rows))
+            }
+            _ => None,
+        }
+    }
+
+    // Writes the database without the row versions only old snapshots see.
+    pub fn save(&self) {
+        let mut db = self.clone();
+        db.vacuum();
+        let encoded: Vec<u8> = serialize(&db).unwrap();
+        let mut f = BufWriter::new(fs::File::create("db.dump").unwrap());
+        f.write_all(&encoded).unwrap();
+    }
+}
+
+// The tables and views a stored query reads, joined ones included.
+fn relations(query: &AST) -> Vec<&str> {
+    match query {
+        AST::Chain {
+            receiver,
+            name,
+            args,
+        } => {
+            let mut relations = relations(receiver);
+            if let (&"join", Some(AST::Ident(table))) = (&&**name, args.first()) {
+                relations.push(table);
+            }
+            relations
+        }
+        AST::MethodCall { table, .. } => vec![table],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+use crate::{ast::OP, parser::run};
+
+#[test]
+fn new() {
+    let db = Database::new();
+    assert_eq!(db.table.len(), 0);
+}
+
+#[test]
+fn execute() {
+    let mut db = Database::new();
+    // Create new table
+    assert_eq!(
+        db.execute(AST::TableDef {
+            name: "Hoge".to_string(),
+            members: vec![],
+            checks: vec![],
+        }),
+        Ok(())
+    );
+
+    assert_eq!(
+        db.execute(AST::MethodCall {
+            table: "Fuga".to_string(),
+            name: "insert".to_string(),
+            args: vec![]
+        }),
+        Err("Table not found!!".to_string())
+    );
+}
+
+#[test]
+fn query() {
+    let mut db = Database::new();
+
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("User.insert(1, \"kuru\")")).unwrap();
+    db.execute(run("User.insert(2, \"knium\")")).unwrap();
+    db.execute(run("User.createIndex(id, hash)")).unwrap();
+
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 2);
+    assert_eq!(
+        db.query(run("User.select().where(id == 2)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![
+            Value::Int(2),
+            Value::StrLiteral("knium".to_string())
+        ]])
+    );
+    assert_eq!(
+        db.query(AST::binop(AST::Number(1), OP::Add, AST::Number(1)))
+            .map(ResultSet::into_rows),
+        Err("Not a query!!".to_string())
+    );
+}
+
+#[test]
+fn transaction() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int}")).unwrap();
+    db.execute(run("Table Order {id: int}")).unwrap();
+
+    db.execute(run("begin;")).unwrap();
+    db.execute(run("User.insert(1)")).unwrap();
+    assert_eq!(
+        db.execute(run("begin;")),
+        Err("Transaction already started!!".to_string())
+    );
+    db.execute(run("rollback;")).unwrap();
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 0);
+
+    db.execute(run("begin;")).unwrap();
+    db.execute(run("User.insert(1)")).unwrap();
+    db.execute(run("commit;")).unwrap();
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+    assert_eq!(
+        db.execute(run("commit;")),
+        Err("No transaction!!".to_string())
+    );
+
+    let result = db.transaction(|tx| {
+        tx.execute(run("User.insert(2)"))?;
+        tx.execute(run("Order.insert(\"oops\")"))
+    });
+    assert_eq!(result, Err("Unmatched type of arg.".to_string()));
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+
+    let result = db.transaction(|tx| {
+        tx.execute(run("User.insert(2)"))?;
+        tx.execute(run("Order.insert(1)"))
+    });
+    assert_eq!(result, Ok(()));
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 2);
+    assert_eq!(db.query(run("Order.select()")).unwrap().len(), 1);
+
+    // The closure's own commit is refused, and everything is rolled back.
+    let result = db.transaction(|tx| {
+        tx.execute(run("User.insert(3)"))?;
+        tx.execute(run("commit;"))
+    });
+    assert_eq!(
+        result,
+        Err("Transaction is ended by Database::transaction!!".to_string())
+    );
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 2);
+    assert_eq!(
+        db.execute(run("commit;")),
+        Err("No transaction!!".to_string())
+    );
+}
+
+#[test]
+fn savepoint() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int}")).unwrap();
+    assert_eq!(
+        db.execute(run("savepoint a;")),
+        Err("No transaction!!".to_string())
+    );
+
+    db.execute(run("begin;")).unwrap();
+    db.execute(run("User.insert(1)")).unwrap();
+    db.execute(run("savepoint a;")).unwrap();
+    db.execute(run("User.insert(2)")).unwrap();
+    db.execute(run("savepoint b;")).unwrap();
+    db.execute(run("User.insert(3)")).unwrap();
+
+    db.execute(run("rollback to a;")).unwrap();
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+    assert_eq!(
+        db.execute(run("rollback to b;")),
+        Err("Savepoint not found!!".to_string())
+    );
+
+    db.execute(run("User.insert(4)")).unwrap();
+    db.execute(run("rollback to a;")).unwrap();
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+
+    db.execute(run("release a;")).unwrap();
+    assert_eq!(
+        db.execute(run("rollback to a;")),
+        Err("Savepoint not found!!".to_string())
+    );
+    db.execute(run("commit;")).unwrap();
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+}
+
+#[test]
+fn snapshot() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int}")).unwrap();
+    db.execute(run("User.insert(1)")).unwrap();
+    db.execute(run("User.insert(2)")).unwrap();
+
+    let snapshot = db.snapshot();
+    db.execute(run("User.insert(3)")).unwrap();
+    db.execute(run("User.delete(0)")).unwrap();
+    assert_eq!(
+        db.query_at(&snapshot, run("User.select()"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(2)]])
+    );
+    assert_eq!(
+        db.query(run("User.select()")).map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(1)], vec![Value::Int(3)]])
+    );
+
+    // Uncommitted writes stay invisible to snapshots until commit.
+    db.execute(run("begin;")).unwrap();
+    db.execute(run("User.insert(4)")).unwrap();
+    assert_eq!(
+        db.query_at(&db.snapshot(), run("User.select()"))
+            .map(ResultSet::into_rows)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 3);
+    db.execute(run("commit;")).unwrap();
+    assert_eq!(
+        db.query_at(&db.snapshot(), run("User.select()"))
+            .map(ResultSet::into_rows)
+            .unwrap()
+            .len(),
+        3
+    );
+
+    // The deleted row is kept while the snapshot seeing it is alive.
+    db.vacuum();
+    assert_eq!(
+        db.query_at(&snapshot, run("User.select()")).unwrap().len(),
+        2
+    );
+    assert!(db.table["User"].reclaimable(db.version));
+    drop(snapshot);
+    db.vacuum();
+    assert!(!db.table["User"].reclaimable(db.version));
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 3);
+}
+
+#[test]
+fn export_json() {
+    let mut db = Database::new();
+    let path = std::env::temp_dir().join("statikk_export.json");
+    let path = path.to_str().unwrap();
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("User.insert(1, \"kuru\")")).unwrap();
+    db.execute(run("User.insert(2, \"knium\")")).unwrap();
+
+    let src = format!("User.select().where(id == 2).exportJson(\"{}\")", path);
+    assert!(!run(&src).is_query());
+    assert_eq!(db.execute(run(&src)), Ok(()));
+    assert_eq!(
+        fs::read_to_string(path).unwrap(),
+        r#"[{"id":2,"name":"knium"}]"#
+    );
+
+    db.execute(run(&format!("User.exportNdjson(\"{}\")", path)))
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(path).unwrap(),
+        "{\"id\":1,\"name\":\"kuru\"}\n{\"id\":2,\"name\":\"knium\"}\n"
+    );
+}
+
+#[test]
+fn table_lifecycle() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int}")).unwrap();
+    db.execute(run("User.insert(1)")).unwrap();
+
+    assert_eq!(
+        db.execute(run("Table User {id: int}")),
+        Err("Table already exists!!".to_string())
+    );
+    assert_eq!(db.execute(run("Table ifNotExists User {id: int}")), Ok(()));
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+    // An invalid definition leaves the old table in place.
+    assert_eq!(
+        db.execute(run("Table replace User {id: string auto}")),
+        Err("Auto column must be int!!".to_string())
+    );
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 1);
+    assert_eq!(db.execute(run("Table replace User {id: int}")), Ok(()));
+    assert_eq!(db.query(run("User.select()")).unwrap().len(), 0);
+
+    db.execute(run("User.insert(2)")).unwrap();
+    db.execute(run("Table Member {id: int}")).unwrap();
+    assert_eq!(
+        db.execute(run("User.renameTo(Member)")),
+        Err("Table already exists!!".to_string())
+    );
+    db.execute(run("drop Member;")).unwrap();
+    assert_eq!(db.execute(run("User.renameTo(Member)")), Ok(()));
+    assert_eq!(db.query(run("Member.select()")).unwrap().len(), 1);
+    assert_eq!(
+        db.query(run("User.select()")).map(ResultSet::into_rows),
+        Err("Table not found!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("drop User;")),
+        Err("Table not found!!".to_string())
+    );
+}
+
+#[test]
+fn introspection() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("Table Order {id: int}")).unwrap();
+    let s = |s: &str| Value::StrLiteral(s.to_string());
+
+    assert_eq!(db.tables(), vec!["Order", "User"]);
+    assert_eq!(
+        db.schema("User"),
+        Some(
+            &[
+                Member::new("id", Type::Int),
+                Member::new("name", Type::StrLiteral)
+            ][..]
+        )
+    );
+    assert_eq!(db.schema("Nope"), None);
+
+    assert_eq!(
+        db.query(run("tables;")).map(ResultSet::into_rows),
+        Ok(vec![vec![s("Order")], vec![s("User")]])
+    );
+    assert_eq!(
+        db.query(run("User.describe();")).map(ResultSet::into_rows),
+        Ok(vec![
+            vec![s("id"), s("int"), s("")],
+            vec![s("name"), s("string"), s("")]
+        ])
+    );
+    assert_eq!(
+        db.query(run("SysTables.select()"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![s("Order")], vec![s("User")]])
+    );
+    assert_eq!(
+        db.query(run("SysColumns.select().where(table == \"User\")"))
+            .map(ResultSet::into_rows),
+        Ok(vec![
+            vec![s("User"), s("id"), s("int"), s("")],
+            vec![s("User"), s("name"), s("string"), s("")]
+        ])
+    );
+}
+
+#[test]
+fn foreign_key() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int}")).unwrap();
+    db.execute(run("Table Item {id: int}")).unwrap();
+    db.execute(run(
+        "Table Order {id: int, user_id: int references User(id) cascade, item_id: int references Item(id)}",
+    ))
+    .unwrap();
+    for src in &[
+        "User.insert(1)",
+        "User.insert(2)",
+        "Item.insert(10)",
+        "Order.insert(100, 1, 10)",
+        "Order.insert(101, 2, 10)",
+    ] {
+        db.execute(run(src)).unwrap();
+    }
+
+    assert_eq!(
+        db.execute(run("Order.insert(102, 3, 10)")),
+        Err("Foreign key violation: Order.user_id = 3 is not in User(id).".to_string())
+    );
+    assert_eq!(
+        db.execute(run("Order.select().where(id == 100).update(item_id: 11)")),
+        Err("Foreign key violation: Order.item_id = 11 is not in Item(id).".to_string())
+    );
+    assert_eq!(
+        db.execute(run("User.select().where(id == 1).update(id: 5)")),
+        Err("Foreign key violation: User(id) = 1 is referenced by Order.user_id.".to_string())
+    );
+    assert_eq!(
+        db.execute(run("Item.select().where(id == 10).delete()")),
+        Err("Foreign key violation: Item(id) = 10 is referenced by Order.item_id.".to_string())
+    );
+    assert_eq!(
+        db.execute(run("drop User;")),
+        Err("Table is referenced by Order!!".to_string())
+    );
+
+    // Deleting a user cascades to their orders.
+    db.execute(run("User.select().where(id == 1).delete()"))
+        .unwrap();
+    assert_eq!(
+        db.query(run("Order.select()")).map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(101), Value::Int(2), Value::Int(10)]])
+    );
+    db.execute(run("Order.update(user_id: user_id - 1, id: id + 1)"))
+        .unwrap_err();
+    db.execute(run("User.insert(1)")).unwrap();
+    db.execute(run("Order.update(user_id: user_id - 1, id: id + 1)"))
+        .unwrap();
+    assert_eq!(
+        db.query(run("Order.select()")).map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(102), Value::Int(1), Value::Int(10)]])
+    );
+    assert_eq!(
+        db.query(run("Order.describe()"))
+            .map(ResultSet::into_rows)
+            .unwrap()[1][2],
+        Value::StrLiteral("references User(id) cascade".to_string())
+    );
+
+    assert_eq!(
+        db.execute(run("Table Bad {id: int references Nope(id)}")),
+        Err("Referenced column Nope(id) not found!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("Table Bad {id: int references User(nope)}")),
+        Err("Referenced column User(nope) not found!!".to_string())
+    );
+    db.execute(run("Table Node {id: int, parent: int references Node(id)}"))
+        .unwrap();
+    db.execute(run("drop Node")).unwrap();
+    assert_eq!(
+        db.execute(run("Table replace User {id: int}")),
+        Err("Table is referenced by Order!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("User.dropColumn(id)")),
+        Err("Column is referenced by Order!!".to_string())
+    );
+
+    // References follow their table and column to new names.
+    db.execute(run("User.renameTo(Person)")).unwrap();
+    db.execute(run("Person.renameColumn(id, pid)")).unwrap();
+    assert_eq!(
+        db.query(run("Order.describe()"))
+            .map(ResultSet::into_rows)
+            .unwrap()[1][2],
+        Value::StrLiteral("references Person(pid) cascade".to_string())
+    );
+    db.execute(run("Order.insert(103, 1, 10)")).unwrap();
+    db.execute(run("Person.select().where(pid == 1).delete()"))
+        .unwrap();
+    assert_eq!(db.query(run("Order.select()")).unwrap().len(), 0);
+
+    // Imported rows are checked like inserted ones.
+    let path = std::env::temp_dir().join("statikk_foreign_key.csv");
+    let path = path.to_str().unwrap();
+    fs::write(path, "104,2,10\n105,99,10\n").unwrap();
+    assert_eq!(
+        db.execute(run(&format!("Order.importCsv(\"{}\")", path))),
+        Err("Foreign key violation: Order.user_id = 99 is not in Person(pid).".to_string())
+    );
+    assert_eq!(db.query(run("Order.select()")).unwrap().len(), 0);
+    fs::write(path, "104,2,10\n").unwrap();
+    db.execute(run(&format!("Order.importCsv(\"{}\")", path)))
+        .unwrap();
+    assert_eq!(db.query(run("Order.select()")).unwrap().len(), 1);
+}
+
+#[test]
+fn sequence() {
+    let mut db = Database::new();
+    db.execute(run("Table Order {id: int auto, total: int}"))
+        .unwrap();
+    db.execute(run("Table Item {id: int, order: int}")).unwrap();
+    db.execute(run("sequence item_seq")).unwrap();
+    assert_eq!(
+        db.execute(run("sequence item_seq")),
+        Err("Sequence already exists!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("Table Bad {id: string auto}")),
+        Err("Auto column must be int!!".to_string())
+    );
+
+    db.execute(run("Order.insert(30)")).unwrap();
+    db.execute(run("Order.insert(40)")).unwrap();
+    db.execute(run("Item.insert(nextval(item_seq), 1)"))
+        .unwrap();
+    db.execute(run("begin")).unwrap();
+    db.execute(run("Item.insert(nextval(item_seq), 2)"))
+        .unwrap();
+    db.execute(run("rollback")).unwrap();
+    db.execute(run("Item.insert(nextval(item_seq) * 10, 2)"))
+        .unwrap();
+    assert_eq!(
+        db.execute(run("Item.insert(nextval(missing), 2)")),
+        Err("Sequence not found!!".to_string())
+    );
+
+    assert_eq!(
+        db.query(run("Order.select()")).map(ResultSet::into_rows),
+        Ok(vec![
+            vec![Value::Int(1), Value::Int(30)],
+            vec![Value::Int(2), Value::Int(40)]
+        ])
+    );
+    assert_eq!(
+        db.query(run("Item.select()")).map(ResultSet::into_rows),
+        Ok(vec![
+            vec![Value::Int(1), Value::Int(1)],
+            vec![Value::Int(30), Value::Int(2)]
+        ])
+    );
+
+    // Counters and sequences survive a save and load.
+    let mut loaded: Database = bincode::deserialize(&serialize(&db).unwrap()).unwrap();
+    loaded.execute(run("Order.insert(50)")).unwrap();
+    loaded
+        .execute(run("Item.insert(nextval(item_seq), 3)"))
+        .unwrap();
+    assert_eq!(
+        loaded
+            .query(run("Order.select().where(total == 50)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(3), Value::Int(50)]])
+    );
+    assert_eq!(
+        loaded
+            .query(run("Item.select().where(order == 3)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(4), Value::Int(3)]])
+    );
+
+    // Queries can't advance a sequence.
+    for src in [
+        "Item.select(nextval(item_seq))",
+        "Item.select().where(id == nextval(item_seq))",
+    ] {
+        assert_eq!(
+            loaded.query(run(src)).map(ResultSet::into_rows),
+            Err("Can't call nextval(item_seq) in a query!!".to_string())
+        );
+    }
+}
+
+#[test]
+fn view() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, age: int}")).unwrap();
+    db.execute(run("View Adults = User.select().where(age >= 18)"))
+        .unwrap();
+    db.execute(run("View Seniors = Adults.select().where(age >= 65)"))
+        .unwrap();
+    assert_eq!(
+        db.execute(run("View User = User.select()")),
+        Err("Table already exists!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("View Missing = Nobody.select()")),
+        Err("Table not found!!".to_string())
+    );
+    for insert in [
+        "User.insert(1, 12)",
+        "User.insert(2, 30)",
+        "User.insert(3, 70)",
+    ] {
+        db.execute(run(insert)).unwrap();
+    }
+
+    let adult = |id, age| vec![Value::Int(id), Value::Int(age)];
+    assert_eq!(
+        db.query(run("Adults.select()")).map(ResultSet::into_rows),
+        Ok(vec![adult(2, 30), adult(3, 70)])
+    );
+    assert_eq!(
+        db.query(run("Adults.select().where(id == 3)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![adult(3, 70)])
+    );
+    assert_eq!(
+        db.query(run("Seniors.select()")).map(ResultSet::into_rows),
+        Ok(vec![adult(3, 70)])
+    );
+
+    assert_eq!(db.tables(), vec!["Adults", "Seniors", "User"]);
+    assert_eq!(
+        db.query(run("Adults.describe()"))
+            .map(ResultSet::into_rows)
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Views defined in a rolled back transaction are gone.
+    db.execute(run("begin;")).unwrap();
+    db.execute(run("View Kids = User.select().where(age < 18)"))
+        .unwrap();
+    db.execute(run("rollback;")).unwrap();
+    assert_eq!(
+        db.query(run("Kids.select()")).map(ResultSet::into_rows),
+        Err("Table not found!!".to_string())
+    );
+
+    // Nothing a view reads can be dropped before the view.
+    assert_eq!(
+        db.execute(run("drop Adults")),
+        Err("Table is read by view Seniors!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("drop User")),
+        Err("Table is read by view Adults!!".to_string())
+    );
+
+    let mut loaded: Database = bincode::deserialize(&serialize(&db).unwrap()).unwrap();
+    loaded.execute(run("drop Seniors")).unwrap();
+    assert_eq!(
+        loaded
+            .query(run("Seniors.select()"))
+            .map(ResultSet::into_rows),
+        Err("Table not found!!".to_string())
+    );
+    assert_eq!(
+        loaded
+            .query(run("Adults.select()"))
+            .map(ResultSet::into_rows),
+        Ok(vec![adult(2, 30), adult(3, 70)])
+    );
+}
+
+#[test]
+fn materialized() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, age: int}")).unwrap();
+    db.execute(run("User.insert(1, 30)")).unwrap();
+    db.execute(run("View Adults = User.select().where(age >= 18)"))
+        .unwrap();
+    db.execute(run("View materialized Cached = User.select()"))
+        .unwrap();
+    db.execute(run("View materialized autoRefresh Live = Adults.select()"))
+        .unwrap();
+    db.execute(run(
+        "View materialized autoRefresh Old = Live.select().where(age > 60)",
+    ))
+    .unwrap();
+
+    db.execute(run("User.insert(2, 70)")).unwrap();
+    let user = |id, age| vec![Value::Int(id), Value::Int(age)];
+    assert_eq!(
+        db.query(run("Cached.select()")).map(ResultSet::into_rows),
+        Ok(vec![user(1, 30)])
+    );
+    assert_eq!(
+        db.query(run("Live.select()")).map(ResultSet::into_rows),
+        Ok(vec![user(1, 30), user(2, 70)])
+    );
+    assert_eq!(
+        db.query(run("Old.select()")).map(ResultSet::into_rows),
+        Ok(vec![user(2, 70)])
+    );
+
+    db.execute(run("Cached.refresh()")).unwrap();
+    assert_eq!(
+        db.query(run("Cached.select()")).map(ResultSet::into_rows),
+        Ok(vec![user(1, 30), user(2, 70)])
+    );
+    assert_eq!(
+        db.execute(run("Cached.insert(3, 40)")),
+        Err("Materialized view is read-only!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("User.refresh()")),
+        Err("Materialized view not found!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("drop Live")),
+        Err("Table is read by view Old!!".to_string())
+    );
+
+    // Refreshed rows are rolled back with the write that caused them.
+    db.execute(run("begin")).unwrap();
+    db.execute(run("User.select().where(id == 2).delete()"))
+        .unwrap();
+    assert_eq!(
+        db.query(run("Old.select()")).map(ResultSet::into_rows),
+        Ok(vec![])
+    );
+    db.execute(run("rollback")).unwrap();
+    assert_eq!(
+        db.query(run("Old.select()")).map(ResultSet::into_rows),
+        Ok(vec![user(2, 70)])
+    );
+
+    // So are materialized views defined inside the transaction.
+    db.execute(run("begin")).unwrap();
+    db.execute(run(
+        "View materialized Young = User.select().where(age < 40)",
+    ))
+    .unwrap();
+    db.execute(run("rollback")).unwrap();
+    assert_eq!(
+        db.query(run("Young.select()")).map(ResultSet::into_rows),
+        Err("Table not found!!".to_string())
+    );
+    assert_eq!(
+        db.execute(run("Young.refresh()")),
+        Err("Materialized view not found!!".to_string())
+    );
+    db.execute(run(
+        "View materialized Young = User.select().where(age < 40)",
+    ))
+    .unwrap();
+    assert_eq!(db.query(run("Young.select()")).unwrap().len(), 1);
+
+    db.execute(run("drop Cached")).unwrap();
+    db.execute(run("View materialized Cached = User.select()"))
+        .unwrap();
+}
+
+#[test]
+fn trigger() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, age: int}")).unwrap();
+    db.execute(run(
+        "Table Log {event: string, id: int, age: int check(age >= 0)}",
+    ))
+    .unwrap();
+    db.execute(run(
+        "trigger created after insert on User = Log.insert(\"insert\", new.id, new.age)",
+    ))
+    .unwrap();
+    db.execute(run(
+        "trigger changed before update on User = Log.insert(\"update\", old.id, old.age)",
+    ))
+    .unwrap();
+    db.execute(run(
+        "trigger removed after delete on User = Log.insert(\"delete\", old.id, old.age)",
+    ))
+    .unwrap();
+    assert_eq!(
+        db.execute(run(
+            "trigger removed after delete on User = Log.insert(old.id)"
+        )),
+        Err("Trigger already exists.".to_string())
+    );
+
+    db.execute(run("User.insert(1, 20)")).unwrap();
+    db.execute(run("User.update(age: age + 1)")).unwrap();
+    db.execute(run("User.select().where(id == 1).delete()"))
+        .unwrap();
+    let log = |event: &str, id, age| {
+        vec![
+            Value::StrLiteral(event.to_string()),
+            Value::Int(id),
+            Value::Int(age),
+        ]
+    };
+    assert_eq!(
+        db.query(run("Log.select()")).map(ResultSet::into_rows),
+        Ok(vec![
+            log("insert", 1, 20),
+            log("update", 1, 20),
+            log("delete", 1, 21)
+        ])
+    );
+
+    // The insert is undone together with the failing trigger.
+    assert_eq!(
+        db.execute(run("User.insert(2, 0 - 1)")),
+        Err("Check constraint `age >= 0` on Log.age failed.".to_string())
+    );
+    assert_eq!(
+        db.query(run("User.select()")).map(ResultSet::into_rows),
+        Ok(vec![])
+    );
+
+    db.execute(run(
+        "trigger again after insert on Log = Log.insert(\"again\", 0, 0)",
+    ))
+    .unwrap();
+    assert_eq!(
+        db.execute(run("User.insert(3, 30)")),
+        Err("Triggers nested too deeply!!".to_string())
+    );
+    db.execute(run("Log.dropTrigger(again)")).unwrap();
+    db.execute(run("User.insert(3, 30)")).unwrap();
+    assert_eq!(
+        db.query(run("User.select()")).map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(3), Value::Int(30)]])
+    );
+
+    // Inside a transaction too, only the failing statement is undone, even
+    // when its trigger fails on a later row.
+    db.execute(run("User.insert(4, 0)")).unwrap();
+    db.execute(run("begin;")).unwrap();
+    db.execute(run("User.insert(5, 50)")).unwrap();
+    assert_eq!(
+        db.execute(run("User.insert(9, 0 - 1)")),
+        Err("Check constraint `age >= 0` on Log.age failed.".to_string())
+    );
+    db.execute(run("User.update(age: age - 1)")).unwrap();
+    assert_eq!(
+        db.execute(run("User.update(age: age - 1)")),
+        Err("Check constraint `age >= 0` on Log.age failed.".to_string())
+    );
+    db.execute(run("commit;")).unwrap();
+    assert_eq!(
+        db.query(run("User.select()")).map(ResultSet::into_rows),
+        Ok(vec![
+            vec![Value::Int(3), Value::Int(29)],
+            vec![Value::Int(4), Value::Int(-1)],
+            vec![Value::Int(5), Value::Int(49)]
+        ])
+    );
+
+    // Imported rows fire insert triggers.
+    let path = std::env::temp_dir().join("statikk_trigger.csv");
+    let path = path.to_str().unwrap();
+    fs::write(path, "6,60\n").unwrap();
+    db.execute(run(&format!("User.importCsv(\"{}\")", path)))
+        .unwrap();
+    assert_eq!(
+        db.query(run("Log.select().where(id == 6)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![log("insert", 6, 60)])
+    );
+
+    // Statements write to the table in place, and a failing one takes back
+    // just its own rows and index entries.
+    db.execute(run("User.createIndex(id, hash)")).unwrap();
+    let table = Arc::as_ptr(&db.table["User"]);
+    db.execute(run("User.insert(7, 70)")).unwrap();
+    assert_eq!(
+        db.execute(run("User.insert(8, 0 - 1)")),
+        Err("Check constraint `age >= 0` on Log.age failed.".to_string())
+    );
+    assert!(std::ptr::eq(table, Arc::as_ptr(&db.table["User"])));
+    assert_eq!(
+        db.query(run("User.select().where(id == 8)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![])
+    );
+    assert_eq!(
+        db.query(run("User.select().where(id == 7)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(7), Value::Int(70)]])
+    );
+}
+
+#[test]
+fn method() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, age: int}")).unwrap();
+    db.execute(run(
+        "method User.adults(minAge: int) = select().where(age >= minAge)",
+    ))
+    .unwrap();
+    db.execute(run("method User.seniors() = adults(65)"))
+        .unwrap();
+    db.execute(run(
+        "method User.birthday(user: int) = select().where(id == user).update(age: age + 1)",
+    ))
+    .unwrap();
+    assert_eq!(
+        db.execute(run("method User.insert(id: int) = select()")),
+        Err("Method already exists.".to_string())
+    );
+    assert_eq!(
+        db.execute(run("method User.loop() = loop()")),
+        Err("Method can't call itself.".to_string())
+    );
+    db.execute(run("method User.ping() = pong()")).unwrap();
+    assert_eq!(
+        db.execute(run("method User.pong() = ping()")),
+        Err("Method can't call itself.".to_string())
+    );
+    assert_eq!(
+        db.execute(run("User.ping()")),
+        Err("Unknown method `pong`!!".to_string())
+    );
+    db.execute(run("User.insert(1, 17)")).unwrap();
+    db.execute(run("User.insert(2, 64)")).unwrap();
+    db.execute(run("User.birthday(1)")).unwrap();
+    db.execute(run("User.birthday(2)")).unwrap();
+
+    let user = |id, age| vec![Value::Int(id), Value::Int(age)];
+    assert!(db.is_query(&run("User.adults(18)")));
+    assert!(!db.is_query(&run("User.birthday(1)")));
+    assert_eq!(
+        db.query(run("User.adults(18)")).map(ResultSet::into_rows),
+        Ok(vec![user(1, 18), user(2, 65)])
+    );
+    assert_eq!(
+        db.query(run("User.adults(18).where(id == 1)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![user(1, 18)])
+    );
+    assert_eq!(
+        db.query(run("User.seniors()")).map(ResultSet::into_rows),
+        Ok(vec![user(2, 65)])
+    );
+    assert_eq!(
+        db.query(run("User.adults(\"18\")"))
+            .map(ResultSet::into_rows),
+        Err("Unmatched type of arg.".to_string())
+    );
+    assert_eq!(
+        db.execute(run("User.adults(18)")),
+        Err("Method returns rows, use query!!".to_string())
+    );
+
+    let mut loaded: Database = bincode::deserialize(&serialize(&db).unwrap()).unwrap();
+    assert_eq!(
+        loaded
+            .query(run("User.seniors()"))
+            .map(ResultSet::into_rows),
+        Ok(vec![user(2, 65)])
+    );
+    loaded.execute(run("User.dropMethod(seniors)")).unwrap();
+    assert_eq!(
+        loaded
+            .query(run("User.seniors()"))
+            .map(ResultSet::into_rows),
+        Err("Not a query!!".to_string())
+    );
+}
+
+#[test]
+fn functions() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("User.insert(1, trim(\"  kuru \"))"))
+        .unwrap();
+    db.execute(run("User.insert(2, null)")).unwrap();
+    db.execute(run("User.insert(3, \"ab\")")).unwrap();
+    assert_eq!(
+        db.execute(run("User.insert(int(\"x\"), \"ab\")")),
+        Err("Cannot cast `x` to int.".to_string())
+    );
+    assert_eq!(
+        db.query(run(
+            "User.select(id, label: concat(upper(name), \"!\")).where(len(name) > 2)"
+        ))
+        .map(ResultSet::into_rows),
+        Ok(vec![vec![
+            Value::Int(1),
+            Value::StrLiteral("KURU!".to_string())
+        ]])
+    );
+    assert_eq!(
+        db.query(run(
+            "User.select(coalesce(name, string(id))).where(id >= 2)"
+        ))
+        .map(ResultSet::into_rows),
+        Ok(vec![
+            vec![Value::StrLiteral("2".to_string())],
+            vec![Value::StrLiteral("ab".to_string())]
+        ])
+    );
+}
+
+#[cfg(test)]
+struct Longest;
+
+#[cfg(test)]
+impl Aggregate for Longest {
+    type State = Option<String>;
+
+    fn init(&self) -> Self::State {
+        None
+    }
+
+    fn step(&self, state: &mut Self::State, args: &[Value]) -> R {
+        if let Value::StrLiteral(s) = &args[0] {
+            if state.as_ref().is_none_or(|longest| s.len() > longest.len()) {
+                *state = Some(s.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&self, state: Self::State) -> Result<Value, String> {
+        Ok(state.map_or(Value::Null, Value::StrLiteral))
+    }
+}
+
+#[test]
+fn register_function() {
+    let mut db = Database::new();
+    let signature = Signature::new(vec![Type::StrLiteral], Type::StrLiteral);
+    db.register_function("shout", signature, |args| match &args[0] {
+        Value::StrLiteral(s) => Ok(Value::StrLiteral(format!("{}!", s))),
+        _ => Ok(Value::Null),
+    })
+    .unwrap();
+    let signature = Signature::new(vec![Type::StrLiteral], Type::StrLiteral);
+    db.register_aggregate("longest", signature, Longest)
+        .unwrap();
+    let signature = Signature::new(vec![Type::StrLiteral], Type::Int);
+    db.register_function("size", signature, |args| match &args[0] {
+        Value::StrLiteral(s) => Ok(Value::Int(s.len() as i32)),
+        _ => Ok(Value::Null),
+    })
+    .unwrap();
+
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("User.insert(1, shout(\"kuru\"))")).unwrap();
+    db.execute(run("User.insert(2, \"knium\")")).unwrap();
+    assert_eq!(
+        db.execute(run("User.insert(3, shout(3))")),
+        Err("Unmatched type of arg to `shout`.".to_string())
+    );
+    assert_eq!(
+        db.query(run("User.select(shout(name)).where(id == 2)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::StrLiteral("knium!".to_string())]])
+    );
+    assert_eq!(
+        db.query(run("User.select(longest(name))"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::StrLiteral("kuru!".to_string())]])
+    );
+    assert_eq!(
+        db.query(run("User.select(longest(name)).where(id > 5)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Null]])
+    );
+    assert_eq!(
+        db.query(run("User.select(size(name)).where(id > 5)"))
+            .map(|result| result.types().into_iter().cloned().collect::<Vec<_>>()),
+        Ok(vec![Type::Int])
+    );
+    assert_eq!(
+        db.query(run("User.select(id, longest(name))"))
+            .map(ResultSet::into_rows),
+        Err("Can't select columns next to aggregates.".to_string())
+    );
+}
+
+#[test]
+fn explain() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("Table Order {id: int, userId: int, total: int}"))
+        .unwrap();
+    db.execute(run("User.createIndex(id, hash)")).unwrap();
+    for insert in [
+        "User.insert(1, \"kuru\")",
+        "User.insert(2, \"knium\")",
+        "Order.insert(1, 1, 30)",
+        "Order.insert(2, 2, 10)",
+        "Order.insert(3, 1, 20)",
+    ] {
+        db.execute(run(insert)).unwrap();
+    }
+
+    let lines = |src| {
+        db.query(run(src)).map(ResultSet::into_rows).map(|rows| {
+            rows.into_iter()
+                .map(|row| row[0].to_string())
+                .collect::<Vec<_>>()
+        })
+    };
+    assert_eq!(
+        lines("explain User.select().where(id == 3)"),
+        Ok(vec!["IndexScan User (id == 3)".to_string()])
+    );
+    assert_eq!(
+        lines("explain User.select(name, total).join(Order, User.id == userId).orderBy(total)"),
+        Ok(vec![
+            "Project name, total".to_string(),
+            "  Sort total".to_string(),
+            "    IndexJoin User.id (User.id == userId)".to_string(),
+            "      SeqScan Order".to_string(),
+        ])
+    );
+
+    let s = |s: &str| Value::StrLiteral(s.to_string());
+    assert_eq!(
+        db.query(run(
+            "User.select(name, total).join(Order, User.id == userId).orderBy(total, desc: true)"
+        ))
+        .map(ResultSet::into_rows),
+        Ok(vec![
+            vec![s("kuru"), Value::Int(30)],
+            vec![s("kuru"), Value::Int(20)],
+            vec![s("knium"), Value::Int(10)],
+        ])
+    );
+    assert_eq!(
+        db.query(run("User.select().join(Order, id == userId)"))
+            .map(ResultSet::into_rows),
+        Err("Ambiguous column `id`.".to_string())
+    );
+    assert_eq!(
+        db.query(run("User.select(name).where(id == 2)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![s("knium")]])
+    );
+    // An overflowing key is no index scan, but fails on the rows it reads.
+    assert_eq!(
+        db.query(run("User.select().where(id == 2147483647 + 1)"))
+            .map(ResultSet::into_rows),
+        Err("Integer overflow.".to_string())
+    );
+    db.execute(run("Table Empty {id: int}")).unwrap();
+    db.execute(run("Empty.createIndex(id, hash)")).unwrap();
+    assert_eq!(
+        db.query(run("Empty.select().where(id == 2147483647 + 1)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![])
+    );
+
+    // With so few rows, scanning both tables beats the index.
+    db.execute(run("analyze")).unwrap();
+    assert_eq!(
+        db.query(run("explain User.select().join(Order, User.id == userId)"))
+            .map(ResultSet::into_rows),
+        Ok(vec![
+            vec![s("NestedLoopJoin (User.id == userId)")],
+            vec![s("  SeqScan User")],
+            vec![s("  SeqScan Order")],
+        ])
+    );
+}
+
+#[test]
+fn analyze() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("User.insert(1, \"kuru\")")).unwrap();
+    db.execute(run("User.insert(2, null)")).unwrap();
+    db.execute(run("User.insert(3, \"kuru\")")).unwrap();
+    assert_eq!(db.statistics("User"), None);
+
+    db.execute(run("analyze User")).unwrap();
+    let s = |s: &str| Value::StrLiteral(s.to_string());
+    assert_eq!(
+        db.query(run("SysStatistics.select()"))
+            .map(ResultSet::into_rows),
+        Ok(vec![
+            vec![
+                s("User"),
+                s("id"),
+                Value::Int(3),
+                Value::Int(3),
+                Value::Int(0),
+                s("1"),
+                s("3")
+            ],
+            vec![
+                s("User"),
+                s("name"),
+                Value::Int(3),
+                Value::Int(1),
+                Value::Int(1),
+                s("kuru"),
+                s("kuru")
+            ],
+        ])
+    );
+    // Statistics stay as they were until the next `analyze`.
+    db.execute(run("User.insert(4, \"knium\")")).unwrap();
+    db.execute(run("User.renameColumn(name, nick)")).unwrap();
+    let statistics = db.statistics("User").unwrap();
+    assert_eq!(statistics.rows, 3);
+    assert_eq!(statistics.columns["nick"].distinct, 1);
+    db.execute(run("analyze")).unwrap();
+    assert_eq!(db.statistics("User").unwrap().rows, 4);
+    assert_eq!(
+        db.execute(run("analyze Order")),
+        Err("Table not found!!".to_string())
+    );
+}
+
+#[test]
+fn result_set() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, name: string}"))
+        .unwrap();
+    db.execute(run("User.insert(1, \"kuru\")")).unwrap();
+    db.execute(run("User.insert(2, null)")).unwrap();
+
+    let result = db
+        .query(run("User.select(id, shout: upper(name)).orderBy(id)"))
+        .unwrap();
+    assert_eq!(result.names(), vec!["id", "shout"]);
+    assert_eq!(result.types(), vec![&Type::Int, &Type::StrLiteral]);
+    let users: Vec<(i32, Option<String>)> = result
+        .iter()
+        .map(|row| Ok((row.get("id")?, row.get("shout")?)))
+        .collect::<Result<_, String>>()
+        .unwrap();
+    assert_eq!(users, vec![(1, Some("KURU".to_string())), (2, None)]);
+
+    // Column types come from the expressions, even with no rows to look at.
+    let result = db
+        .query(run(
+            "User.select(id > 1, twice: id * 2, shout: upper(name)).where(id > 5)",
+        ))
+        .unwrap();
+    assert!(result.is_empty());
+    assert_eq!(
+        result.types(),
+        vec![&Type::Bool, &Type::Int, &Type::StrLiteral]
     );
 }
@@ -0,0 +1,534 @@
+use crate::primitive::Type;
+use crate::table::{Table, Value, Values};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+type Builtin = fn(&str, &[Value]) -> Result<Value, String>;
+
+// Scalar functions callable in expressions, e.g. `upper(name)`.
+const BUILTINS: &[(&str, Builtin)] = &[
+    ("len", len),
+    ("upper", upper),
+    ("lower", lower),
+    ("trim", trim),
+    ("substr", substr),
+    ("concat", concat),
+    ("abs", abs),
+    ("min", min),
+    ("max", max),
+    ("round", round),
+    ("coalesce", coalesce),
+    ("if", if_),
+    ("int", int),
+    ("string", string),
+];
+
+pub fn lookup(name: &str) -> Option<Builtin> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, f)| *f)
+}
+
+pub fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    if let Some(f) = lookup(name) {
+        return f(name, args);
+    }
+    let function = registered(name).ok_or_else(|| format!("Unknown function `{}`.", name))?;
+    match &function.kind {
+        Kind::Scalar(f) => {
+            function.signature.check(name, args)?;
+            function.signature.returned(name, f(args)?)
+        }
+        Kind::Aggregate(_) => Err(format!("Aggregate `{}` can only be selected.", name)),
+    }
+}
+
+// The types a registered function takes and returns. Null is accepted
+// everywhere.
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub params: Vec<Type>,
+    pub returns: Type,
+}
+
+impl Signature {
+    pub fn new(params: Vec<Type>, returns: Type) -> Self {
+        Self { params, returns }
+    }
+
+    fn check(&self, name: &str, args: &[Value]) -> Result<(), String> {
+        arity(name, args, self.params.len()..=self.params.len())?;
+        for (typ, arg) in self.params.iter().zip(args) {
+            if !Table::has_type(typ, arg) {
+                return Err(unmatched(name));
+            }
+        }
+        Ok(())
+    }
+
+    fn returned(&self, name: &str, value: Value) -> Result<Value, String> {
+        if Table::has_type(&self.returns, &value) {
+            Ok(value)
+        } else {
+            Err(format!("Function `{}` returned the wrong type.", name))
+        }
+    }
+}
+
+// A user aggregate folds the arguments of every selected row into one value.
+pub trait Aggregate: Send + Sync {
+    type State;
+
+    fn init(&self) -> Self::State;
+    fn step(&self, state: &mut Self::State, args: &[Value]) -> Result<(), String>;
+    fn finish(&self, state: Self::State) -> Result<Value, String>;
+}
+
+type Scalar = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+// An aggregate run over the arguments of all rows at once.
+type Fold = Arc<dyn Fn(&[Values]) -> Result<Value, String> + Send + Sync>;
+
+#[derive(Clone)]
+enum Kind {
+    Scalar(Scalar),
+    Aggregate(Fold),
+}
+
+#[derive(Clone)]
+struct Function {
+    signature: Signature,
+    kind: Kind,
+}
+
+// Functions registered by the embedding application. Cloning shares them.
+#[derive(Clone, Default)]
+pub struct Registry {
+    functions: Arc<BTreeMap<String, Function>>,
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.functions.keys()).finish()
+    }
+}
+
+impl Registry {
+    fn register(&mut self, name: &str, function: Function) -> Result<(), String> {
+        if lookup(name).is_some() || self.functions.contains_key(name) {
+            return Err(format!("Function `{}` already exists.", name));
+        }
+        Arc::make_mut(&mut self.functions).insert(name.to_string(), function);
+        Ok(())
+    }
+
+    pub fn scalar<F>(&mut self, name: &str, signature: Signature, f: F) -> Result<(), String>
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let kind = Kind::Scalar(Arc::new(f));
+        self.register(name, Function { signature, kind })
+    }
+
+    pub fn aggregate<A>(
+        &mut self,
+        name: &str,
+        signature: Signature,
+        aggregate: A,
+    ) -> Result<(), String>
+    where
+        A: Aggregate + 'static,
+    {
+        let fold = move |rows: &[Values]| {
+            let mut state = aggregate.init();
+            for args in rows {
+                aggregate.step(&mut state, args)?;
+            }
+            aggregate.finish(state)
+        };
+        let kind = Kind::Aggregate(Arc::new(fold));
+        self.register(name, Function { signature, kind })
+    }
+
+    // Makes the functions callable from expressions evaluated inside `f`.
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        struct Restore(Option<Registry>);
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let _restore = Restore(previous);
+        f()
+    }
+}
+
+thread_local! {
+    // The registry of the database running a statement on this thread, so
+    // evaluation deep inside tables doesn't need it passed along.
+    static CURRENT: RefCell<Option<Registry>> = const { RefCell::new(None) };
+}
+
+fn registered(name: &str) -> Option<Function> {
+    CURRENT.with(|current| {
+        let current = current.borrow();
+        current.as_ref()?.functions.get(name).cloned()
+    })
+}
+
+// The type `name` returns given the types of its arguments, where known.
+pub fn returns(name: &str, args: &[Option<Type>]) -> Option<Type> {
+    match name {
+        "len" | "abs" | "round" | "int" => Some(Type::Int),
+        "upper" | "lower" | "trim" | "substr" | "concat" | "string" => Some(Type::StrLiteral),
+        "min" | "max" | "coalesce" => args.iter().flatten().next().cloned(),
+        "if" => args.iter().skip(1).flatten().next().cloned(),
+        _ => registered(name).map(|function| function.signature.returns),
+    }
+}
+
+pub fn is_aggregate(name: &str) -> bool {
+    matches!(
+        registered(name),
+        Some(Function {
+            kind: Kind::Aggregate(_),
+            ..
+        })
+    )
+}
+
+// Runs aggregate `name` over the arguments it got for each row.
+pub fn aggregate(name: &str, rows: &[Values]) -> Result<Value, String> {
+    match registered(name) {
+        Some(Function {
+            signature,
+            kind: Kind::Aggregate(fold),
+        }) => {
+            for args in rows {
+                signature.check(name, args)?;
+            }
+            signature.returned(name, fold(rows)?)
+        }
+        _ => Err(format!("Unknown aggregate `{}`.", name)),
+    }
+}
+
+fn arity(name: &str, args: &[Value], range: std::ops::RangeInclusive<usize>) -> Result<(), String> {
+    if range.contains(&args.len()) {
+        Ok(())
+    } else {
+        Err(format!("Wrong number of arguments to `{}`.", name))
+    }
+}
+
+fn unmatched(name: &str) -> String {
+    format!("Unmatched type of arg to `{}`.", name)
+}
+
+// Applies `f` to a single string argument, passing null through.
+fn map_str(name: &str, args: &[Value], f: fn(&str) -> Value) -> Result<Value, String> {
+    arity(name, args, 1..=1)?;
+    match &args[0] {
+        Value::StrLiteral(s) => Ok(f(s)),
+        Value::Null => Ok(Value::Null),
+        _ => Err(unmatched(name)),
+    }
+}
+
+fn len(name: &str, args: &[Value]) -> Result<Value, String> {
+    map_str(name, args, |s| Value::Int(s.chars().count() as i32))
+}
+
+fn upper(name: &str, args: &[Value]) -> Result<Value, String> {
+    map_str(name, args, |s| Value::StrLiteral(s.to_uppercase()))
+}
+
+fn lower(name: &str, args: &[Value]) -> Result<Value, String> {
+    map_str(name, args, |s| Value::StrLiteral(s.to_lowercase()))
+}
+
+fn trim(name: &str, args: &[Value]) -> Result<Value, String> {
+    map_str(name, args, |s| Value::StrLiteral(s.trim().to_string()))
+}
+
+// `substr(s, start[, length])`, counting characters from 1.
+fn substr(name: &str, args: &[Value]) -> Result<Value, String> {
+    arity(name, args, 2..=3)?;
+    let length = match args.get(2) {
+        Some(Value::Int(length)) => (*length).max(0) as usize,
+        Some(_) => return Err(unmatched(name)),
+        None => usize::MAX,
+    };
+    match (&args[0], &args[1]) {
+        (Value::StrLiteral(s), Value::Int(start)) => {
+            let start = (*start).max(1) as usize - 1;
+            Ok(Value::StrLiteral(
+                s.chars().skip(start).take(length).collect(),
+            ))
+        }
+        (Value::Null, _) => Ok(Value::Null),
+        _ => Err(unmatched(name)),
+    }
+}
+
+// Joins every argument as text, skipping nulls.
+fn concat(_: &str, args: &[Value]) -> Result<Value, String> {
+    let s = args
+        .iter()
+        .filter(|value| **value != Value::Null)
+        .map(|value| value.to_string())
+        .collect();
+    Ok(Value::StrLiteral(s))
+}
+
+fn abs(name: &str, args: &[Value]) -> Result<Value, String> {
+    arity(name, args, 1..=1)?;
+    match args[0] {
+        Value::Int(i) => i
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| format!("Overflow in `{}`.", name)),
+        Value::Null => Ok(Value::Null),
+        _ => Err(unmatched(name)),
+    }
+}
+
+// Picks among arguments of one type, ignoring nulls.
+fn pick(name: &str, args: &[Value], greater: bool) -> Result<Value, String> {
+    arity(name, args, 1..=usize::MAX)?;
+    let mut best = Value::Null;
+    for value in args {
+        best = match (&best, value) {
+            (_, Value::Null) => best,
+            (Value::Null, _) => value.clone(),
+            (Value::Int(a), Value::Int(b)) if (b > a) == greater => value.clone(),
+            (Value::StrLiteral(a), Value::StrLiteral(b)) if (b > a) == greater => value.clone(),
+            (Value::Int(_), Value::Int(_)) | (Value::StrLiteral(_), Value::StrLiteral(_)) => best,
+            _ => return Err(unmatched(name)),
+        };
+    }
+    Ok(best)
+}
+
+fn min(name: &str, args: &[Value]) -> Result<Value, String> {
+    pick(name, args, false)
+}
+
+fn max(name: &str, args: &[Value]) -> Result<Value, String> {
+    pick(name, args, true)
+}
+
+// `round(x, places)` rounds half away from zero; with only integers, just
+// negative places change anything, e.g. `round(1250, -2)` is 1300.
+fn round(name: &str, args: &[Value]) -> Result<Value, String> {
+    arity(name, args, 1..=2)?;
+    let places = match args.get(1) {
+        Some(Value::Int(places)) => *places,
+        Some(_) => return Err(unmatched(name)),
+        None => 0,
+    };
+    match args[0] {
+        Value::Int(i) if places < 0 => {
+            let unit = 10i64.pow(places.unsigned_abs().min(10));
+            let rounded = (i64::from(i).abs() + unit / 2) / unit * unit * i64::from(i.signum());
+            i32::try_from(rounded)
+                .map(Value::Int)
+                .map_err(|_| format!("Overflow in `{}`.", name))
+        }
+        Value::Int(i) => Ok(Value::Int(i)),
+        Value::Null => Ok(Value::Null),
+        _ => Err(unmatched(name)),
+    }
+}
+
+fn coalesce(_: &str, args: &[Value]) -> Result<Value, String> {
+    let value = args.iter().find(|value| **value != Value::Null);
+    Ok(value.cloned().unwrap_or(Value::Null))
+}
+
+// `if(condition, then, else)`.
+fn if_(name: &str, args: &[Value]) -> Result<Value, String> {
+    arity(name, args, 3..=3)?;
+    match args[0] {
+        Value::Bool(true) => Ok(args[1].clone()),
+        Value::Bool(false) | Value::Null => Ok(args[2].clone()),
+        _ => Err(unmatched(name)),
+    }
+}
+
+fn int(name: &str, args: &[Value]) -> Result<Value, String> {
+    arity(name, args, 1..=1)?;
+    match &args[0] {
+        Value::StrLiteral(s) => match s.trim().parse() {
+            Ok(i) => Ok(Value::Int(i)),
+            Err(_) => Err(format!("Cannot cast `{}` to int.", s)),
+        },
+        Value::Bool(b) => Ok(Value::Int(*b as i32)),
+        value => Ok(value.clone()),
+    }
+}
+
+fn string(name: &str, args: &[Value]) -> Result<Value, String> {
+    arity(name, args, 1..=1)?;
+    match &args[0] {
+        Value::Null => Ok(Value::Null),
+        value => Ok(Value::StrLiteral(value.to_string())),
+    }
+}
+
+#[cfg(test)]
+fn s(s: &str) -> Value {
+    Value::StrLiteral(s.to_string())
+}
+
+#[test]
+fn strings() {
+    assert_eq!(call("len", &[s("kürü")]), Ok(Value::Int(4)));
+    assert_eq!(call("upper", &[s("kuru")]), Ok(s("KURU")));
+    assert_eq!(call("lower", &[Value::Null]), Ok(Value::Null));
+    assert_eq!(call("trim", &[s("  kuru ")]), Ok(s("kuru")));
+    assert_eq!(
+        call("substr", &[s("statikk"), Value::Int(2), Value::Int(3)]),
+        Ok(s("tat"))
+    );
+    assert_eq!(call("substr", &[s("statikk"), Value::Int(5)]), Ok(s("ikk")));
+    assert_eq!(
+        call("concat", &[s("id-"), Value::Int(1), Value::Null]),
+        Ok(s("id-1"))
+    );
+    assert_eq!(
+        call("len", &[Value::Int(1)]),
+        Err("Unmatched type of arg to `len`.".to_string())
+    );
+    assert_eq!(
+        call("upper", &[]),
+        Err("Wrong number of arguments to `upper`.".to_string())
+    );
+    assert_eq!(
+        call("nope", &[]),
+        Err("Unknown function `nope`.".to_string())
+    );
+}
+
+#[test]
+fn math() {
+    assert_eq!(call("abs", &[Value::Int(-3)]), Ok(Value::Int(3)));
+    assert_eq!(
+        call("abs", &[Value::Int(i32::MIN)]),
+        Err("Overflow in `abs`.".to_string())
+    );
+    let args = [Value::Int(3), Value::Null, Value::Int(-1)];
+    assert_eq!(call("min", &args), Ok(Value::Int(-1)));
+    assert_eq!(call("max", &args), Ok(Value::Int(3)));
+    assert_eq!(call("max", &[s("a"), s("b")]), Ok(s("b")));
+    assert_eq!(
+        call("max", &[s("a"), Value::Int(1)]),
+        Err("Unmatched type of arg to `max`.".to_string())
+    );
+    assert_eq!(
+        call("round", &[Value::Int(1250), Value::Int(-2)]),
+        Ok(Value::Int(1300))
+    );
+    assert_eq!(
+        call("round", &[Value::Int(-1249), Value::Int(-2)]),
+        Ok(Value::Int(-1200))
+    );
+    assert_eq!(call("round", &[Value::Int(7)]), Ok(Value::Int(7)));
+}
+
+#[test]
+fn conditionals_and_casts() {
+    assert_eq!(
+        call("coalesce", &[Value::Null, Value::Int(2), Value::Int(3)]),
+        Ok(Value::Int(2))
+    );
+    assert_eq!(call("coalesce", &[Value::Null]), Ok(Value::Null));
+    assert_eq!(
+        call("if", &[Value::Bool(false), s("yes"), s("no")]),
+        Ok(s("no"))
+    );
+    assert_eq!(call("int", &[s(" 42 ")]), Ok(Value::Int(42)));
+    assert_eq!(
+        call("int", &[s("x")]),
+        Err("Cannot cast `x` to int.".to_string())
+    );
+    assert_eq!(call("int", &[Value::Bool(true)]), Ok(Value::Int(1)));
+    assert_eq!(call("string", &[Value::Int(42)]), Ok(s("42")));
+}
+
+#[cfg(test)]
+struct Sum;
+
+#[cfg(test)]
+impl Aggregate for Sum {
+    type State = i32;
+
+    fn init(&self) -> i32 {
+        0
+    }
+
+    fn step(&self, state: &mut i32, args: &[Value]) -> Result<(), String> {
+        if let Value::Int(i) = args[0] {
+            *state += i;
+        }
+        Ok(())
+    }
+
+    fn finish(&self, state: i32) -> Result<Value, String> {
+        Ok(Value::Int(state))
+    }
+}
+
+#[test]
+fn registry() {
+    let mut registry = Registry::default();
+    let double = |args: &[Value]| match args[0] {
+        Value::Int(i) => Ok(Value::Int(i * 2)),
+        _ => Ok(Value::Null),
+    };
+    registry
+        .scalar("double", Signature::new(vec![Type::Int], Type::Int), double)
+        .unwrap();
+    registry
+        .scalar(
+            "broken",
+            Signature::new(vec![], Type::Int),
+            |_: &[Value]| Ok(s("x")),
+        )
+        .unwrap();
+    registry
+        .aggregate("total", Signature::new(vec![Type::Int], Type::Int), Sum)
+        .unwrap();
+    assert_eq!(
+        registry.scalar("upper", Signature::new(vec![], Type::Int), double),
+        Err("Function `upper` already exists.".to_string())
+    );
+
+    assert_eq!(
+        call("double", &[Value::Int(2)]),
+        Err("Unknown function `double`.".to_string())
+    );
+    registry.scope(|| {
+        assert_eq!(call("double", &[Value::Int(2)]), Ok(Value::Int(4)));
+        assert_eq!(
+            call("double", &[s("2")]),
+            Err("Unmatched type of arg to `double`.".to_string())
+        );
+        assert_eq!(
+            call("broken", &[]),
+            Err("Function `broken` returned the wrong type.".to_string())
+        );
+        assert_eq!(
+            call("total", &[Value::Int(1)]),
+            Err("Aggregate `total` can only be selected.".to_string())
+        );
+        let rows = vec![vec![Value::Int(1)], vec![Value::Null], vec![Value::Int(2)]];
+        assert!(is_aggregate("total"));
+        assert_eq!(aggregate("total", &rows), Ok(Value::Int(3)));
+    });
+    assert!(!is_aggregate("total"));
+}
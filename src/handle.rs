@@ -0,0 +1,227 @@
+use crate::{ast::AST, database::Database, result::ResultSet};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+
+// A cloneable database handle which can be shared between threads. Writes
+// of rows lock the tables they touch, so writers to different tables run
+// side by side; schema changes and transactions lock the whole catalog.
+// Readers only wait while a write is published.
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseHandle {
+    db: Arc<RwLock<Database>>,
+    // Shared by writes of rows, held alone by every other write.
+    catalog: Arc<RwLock<()>>,
+    // One lock per table, held by the write of rows to it.
+    tables: Arc<Mutex<BTreeMap<String, Arc<Mutex<()>>>>>,
+}
+
+impl DatabaseHandle {
+    pub fn new(db: Database) -> Self {
+        Self {
+            db: Arc::new(RwLock::new(db)),
+            ..Self::default()
+        }
+    }
+
+    // Reads the committed state. The lock is only held while the database is
+    // cloned, which shares its tables, so long scans never block writers.
+    // A table written while a scan still reads it is copied once for the writer.
+    pub fn query(&self, tree: AST) -> Result<ResultSet, String> {
+        let db = self.cloned();
+        db.query_at(&db.snapshot(), tree)
+    }
+
+    // Transaction statements would interleave with other threads' writes,
+    // so they have to go through `transaction` instead.
+    pub fn execute(&self, tree: AST) -> Result<(), String> {
+        match tree {
+            AST::Begin
+            | AST::Commit
+            | AST::Rollback
+            | AST::Savepoint(_)
+            | AST::RollbackTo(_)
+            | AST::Release(_) => Err("Use DatabaseHandle::transaction!!".to_string()),
+            tree => {
+                let catalog = self.catalog.read().unwrap_or_else(PoisonError::into_inner);
+                let footprint = self.read().footprint(&tree);
+                let names = match footprint {
+                    Some(names) => names,
+                    None => {
+                        drop(catalog);
+                        let _catalog = self.catalog.write().unwrap_or_else(PoisonError::into_inner);
+                        let mut db = self.db.write().unwrap_or_else(PoisonError::into_inner);
+                        return db.execute(tree);
+                    }
+                };
+                // Taken in name order, so two writers never wait on each other.
+                let locks: Vec<Arc<Mutex<()>>> = {
+                    let mut tables = self.tables.lock().unwrap_or_else(PoisonError::into_inner);
+                    names
+                        .iter()
+                        .map(|name| tables.entry(name.clone()).or_default().clone())
+                        .collect()
+                };
+                let _held: Vec<MutexGuard<()>> = locks
+                    .iter()
+                    .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner))
+                    .collect();
+                let mut db = self.cloned();
+                let since = db.version();
+                db.execute(tree)?;
+                let mut published = self.db.write().unwrap_or_else(PoisonError::into_inner);
+                published.merge(db, &names, since);
+                Ok(())
+            }
+        }
+    }
+
+    // Runs `f` on a copy of the database, so readers aren't blocked while it
+    // runs, and publishes the copy once `f` has committed or rolled back.
+    // Other writers wait until then.
+    pub fn transaction<T, F>(&self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut Database) -> Result<T, String>,
+    {
+        let _catalog = self.catalog.write().unwrap_or_else(PoisonError::into_inner);
+        let mut db = self.cloned();
+        let result = db.transaction(f);
+        *self.db.write().unwrap_or_else(PoisonError::into_inner) = db;
+        result
+    }
+
+    fn cloned(&self) -> Database {
+        self.read().clone()
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Database> {
+        self.db.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+use crate::parser::run;
+
+#[test]
+fn send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<DatabaseHandle>();
+}
+
+#[test]
+fn execute() {
+    let handle = DatabaseHandle::default();
+    handle.execute(run("Table User {id: int}")).unwrap();
+    assert_eq!(
+        handle.execute(run("begin;")),
+        Err("Use DatabaseHandle::transaction!!".to_string())
+    );
+    assert_eq!(
+        handle.execute(run("User.foo()")),
+        Err("Unknown method `foo`!!".to_string())
+    );
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let handle = handle.clone();
+            std::thread::spawn(move || -> Result<(), String> {
+                for _ in 0..25 {
+                    handle.execute(AST::MethodCall {
+                        table: "User".to_string(),
+                        name: "insert".to_string(),
+                        args: vec![AST::Number(i)],
+                    })?;
+                    handle
+                        .query(run("User.select()"))
+                        .map(ResultSet::into_rows)?;
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), Ok(()));
+    }
+    assert_eq!(handle.query(run("User.select()")).unwrap().len(), 100);
+}
+
+#[test]
+fn table_locks() {
+    use crate::{function::Signature, primitive::Type};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    // Returns once two statements are running it at the same time.
+    let mut db = Database::new();
+    let arrived = Arc::new(AtomicUsize::new(0));
+    let signature = Signature::new(vec![Type::Int], Type::Int);
+    db.register_function("meet", signature, move |args| {
+        arrived.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        while arrived.load(Ordering::SeqCst) < 2 {
+            if start.elapsed() > Duration::from_secs(5) {
+                return Err("Writers took turns!!".to_string());
+            }
+            std::thread::yield_now();
+        }
+        Ok(args[0].clone())
+    })
+    .unwrap();
+    let handle = DatabaseHandle::new(db);
+    handle.execute(run("Table User {id: int}")).unwrap();
+    handle.execute(run("Table Item {id: int}")).unwrap();
+
+    // Writers to different tables don't wait for each other.
+    let threads: Vec<_> = ["User", "Item"]
+        .iter()
+        .map(|table| {
+            let handle = handle.clone();
+            let src = format!("{}.insert(meet(1))", table);
+            std::thread::spawn(move || handle.execute(run(&src)))
+        })
+        .collect();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), Ok(()));
+    }
+    assert_eq!(handle.query(run("User.select()")).unwrap().len(), 1);
+    assert_eq!(handle.query(run("Item.select()")).unwrap().len(), 1);
+
+    // Both writes are committed, and later ones go on from there.
+    handle.execute(run("User.select().delete()")).unwrap();
+    handle.execute(run("Item.insert(2)")).unwrap();
+    assert_eq!(handle.query(run("User.select()")).unwrap().len(), 0);
+    assert_eq!(handle.query(run("Item.select()")).unwrap().len(), 2);
+}
+
+#[test]
+fn transaction() {
+    let handle = DatabaseHandle::default();
+    handle.execute(run("Table User {id: int}")).unwrap();
+    let result = handle.transaction(|tx| {
+        tx.execute(run("User.insert(1)"))?;
+        tx.execute(run("User.insert(\"oops\")"))
+    });
+    assert_eq!(result, Err("Unmatched type of arg.".to_string()));
+    assert_eq!(handle.query(run("User.select()")).unwrap().len(), 0);
+
+    // Readers go on while a transaction runs, and see it once committed.
+    let reader = handle.clone();
+    let result = handle.transaction(|tx| {
+        tx.execute(run("User.insert(1)"))?;
+        assert_eq!(reader.query(run("User.select()")).unwrap().len(), 0);
+        Ok(())
+    });
+    assert_eq!(result, Ok(()));
+    assert_eq!(handle.query(run("User.select()")).unwrap().len(), 1);
+
+    // A panicking transaction leaves the handle usable and the data as it was.
+    let panicking = handle.clone();
+    let thread = std::thread::spawn(move || {
+        panicking.transaction(|tx| -> Result<(), String> {
+            tx.execute(run("User.insert(2)"))?;
+            panic!("transaction panicked");
+        })
+    });
+    assert!(thread.join().is_err());
+    handle.execute(run("User.insert(3)")).unwrap();
+    assert_eq!(handle.query(run("User.select()")).unwrap().len(), 2);
+}
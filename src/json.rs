@@ -0,0 +1,128 @@
+use crate::ast::Member;
+use crate::table::{Value, Values};
+use serde_json::{Map, Value as Json};
+use std::convert::TryFrom;
+
+// A row as an object keyed by member names.
+pub fn object(members: &[Member], row: &[Value]) -> Json {
+    let mut object = Map::new();
+    for (member, value) in members.iter().zip(row) {
+        let value = match value {
+            Value::Int(i) => Json::from(*i),
+            Value::StrLiteral(s) => Json::from(s.clone()),
+            Value::Bool(b) => Json::from(*b),
+            Value::Null => Json::Null,
+        };
+        object.insert(member.field.clone(), value);
+    }
+    Json::Object(object)
+}
+
+pub fn array(members: &[Member], rows: &[Values]) -> String {
+    let rows = rows.iter().map(|row| object(members, row)).collect();
+    Json::Array(rows).to_string()
+}
+
+// One object per line.
+pub fn ndjson(members: &[Member], rows: &[Values]) -> String {
+    rows.iter()
+        .map(|row| object(members, row).to_string() + "\n")
+        .collect()
+}
+
+// Writes `rows` as one JSON array, or as NDJSON for `exportNdjson`.
+pub fn write(path: &str, method: &str, members: &[Member], rows: &[Values]) -> Result<(), String> {
+    let out = match method {
+        "exportNdjson" => ndjson(members, rows),
+        _ => array(members, rows),
+    };
+    std::fs::write(path, out).map_err(|e| format!("{}: {}", path, e))
+}
+
+// Reads an object back into a row ordered like `members`. Values keep the
+// JSON type they were written with, so the caller still has to type check.
+pub fn row(members: &[Member], line: &str) -> Result<Values, String> {
+    let object = match serde_json::from_str(line) {
+        Ok(Json::Object(object)) => object,
+        Ok(_) => return Err("expected an object".to_string()),
+        Err(e) => return Err(e.to_string()),
+    };
+    if let Some(key) = object
+        .keys()
+        .find(|key| members.iter().all(|m| &m.field != *key))
+    {
+        return Err(format!("unknown column `{}`", key));
+    }
+    let mut row = vec![];
+    for member in members {
+        let value = match object.get(&member.field) {
+            Some(Json::Number(n)) => match n.as_i64().map(i32::try_from) {
+                Some(Ok(i)) => Value::Int(i),
+                _ => return Err(format!("column `{}`: {} is not an int", member.field, n)),
+            },
+            Some(Json::String(s)) => Value::StrLiteral(s.clone()),
+            Some(Json::Bool(b)) => Value::Bool(*b),
+            Some(Json::Null) => Value::Null,
+            Some(value) => {
+                return Err(format!(
+                    "column `{}`: unsupported value {}",
+                    member.field, value
+                ))
+            }
+            None => return Err(format!("missing column `{}`", member.field)),
+        };
+        row.push(value);
+    }
+    Ok(row)
+}
+
+#[cfg(test)]
+use crate::primitive::Type;
+
+#[cfg(test)]
+fn members() -> Vec<Member> {
+    vec![
+        Member::new("id", Type::Int),
+        Member::new("name", Type::StrLiteral),
+    ]
+}
+
+#[test]
+fn export() {
+    let rows = vec![
+        vec![Value::Int(1), Value::StrLiteral("kuru".to_string())],
+        vec![Value::Int(2), Value::StrLiteral("knium".to_string())],
+    ];
+    assert_eq!(
+        array(&members(), &rows),
+        r#"[{"id":1,"name":"kuru"},{"id":2,"name":"knium"}]"#
+    );
+    assert_eq!(
+        ndjson(&members(), &rows),
+        "{\"id\":1,\"name\":\"kuru\"}\n{\"id\":2,\"name\":\"knium\"}\n"
+    );
+}
+
+#[test]
+fn import() {
+    assert_eq!(
+        row(&members(), r#"{"name": "kuru", "id": 1}"#),
+        Ok(vec![Value::Int(1), Value::StrLiteral("kuru".to_string())])
+    );
+    assert_eq!(
+        row(&members(), r#"{"id": 1}"#),
+        Err("missing column `name`".to_string())
+    );
+    assert_eq!(
+        row(&members(), r#"{"id": 1, "name": "kuru", "age": 3}"#),
+        Err("unknown column `age`".to_string())
+    );
+    assert_eq!(
+        row(&members(), r#"{"id": 1.5, "name": "kuru"}"#),
+        Err("column `id`: 1.5 is not an int".to_string())
+    );
+    assert_eq!(
+        row(&members(), "[1]"),
+        Err("expected an object".to_string())
+    );
+}
@@ -1,7 +1,17 @@
 pub mod ast;
+pub mod csv;
 pub mod database;
+pub mod function;
+pub mod handle;
+pub mod json;
+pub mod optimize;
 pub mod parser;
+pub mod plan;
 pub mod primitive;
+pub mod result;
+pub mod statement;
+pub mod statistics;
 pub mod table;
 pub mod token;
 pub mod tokenizer;
+pub mod vm;
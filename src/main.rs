@@ -1,4 +1,3 @@
-use bincode;
 use statikk_db::database::Database;
 use statikk_db::parser::Parser;
 use statikk_db::tokenizer::Tokenizer;
@@ -38,8 +37,28 @@ fn main() {
             }
             _ => (),
         };
-        let tokens = Tokenizer::new(s.trim()).lex_all();
-        let tree = Parser::new(tokens).parse();
+        let tree = match Tokenizer::new(s.trim())
+            .lex_all()
+            .and_then(|tokens| Parser::new(tokens).parse())
+        {
+            Ok(tree) => tree,
+            Err(msg) => {
+                println!("{}", msg);
+                continue;
+            }
+        };
+        if db.is_query(&tree) {
+            match db.query(tree) {
+                Ok(result) => {
+                    println!("{:?}", result.names());
+                    for row in &result {
+                        println!("{:?}", row.values());
+                    }
+                }
+                Err(msg) => println!("{}", msg),
+            }
+            continue;
+        }
         if let Err(msg) = db.execute(tree) {
             println!("{}", msg);
         } else {
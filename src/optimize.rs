@@ -0,0 +1,202 @@
+use crate::ast::{AST, OP};
+use crate::function;
+use crate::table::Value;
+
+// Rewrites an expression into an equivalent one which is cheaper to evaluate
+// on every row: constant subtrees are folded, so `age > 1 + 2 * 3` becomes
+// `age > 7`, and constants move right of comparisons, so `3 < age` becomes
+// `age > 3`. Subtrees which fail to evaluate are left to fail at run time.
+pub fn fold(tree: AST) -> AST {
+    tree.try_map(&mut |node| Ok(fold_node(node))).unwrap()
+}
+
+fn fold_node(node: AST) -> AST {
+    match node {
+        AST::BinOP(ref left, _, ref right) if constant(left) && constant(right) => evaluate(node),
+        AST::BinOP(left, op, right) if comparison(&op) && constant(&left) && !constant(&right) => {
+            AST::BinOP(right, op.flip(), left)
+        }
+        // Only built-ins are known to give the same result every time.
+        AST::Call(ref name, ref args)
+            if function::lookup(name).is_some() && args.iter().all(constant) =>
+        {
+            evaluate(node)
+        }
+        AST::Call(name, mut args) if name == "if" && args.len() == 3 => {
+            let taken = match args[0] {
+                AST::Bool(true) => 1,
+                AST::Bool(false) | AST::Null => 2,
+                _ => return AST::Call(name, args),
+            };
+            // Arguments are all evaluated, so the other branch must not fail.
+            if constant(&args[3 - taken]) {
+                return args.swap_remove(taken);
+            }
+            AST::Call(name, args)
+        }
+        node => node,
+    }
+}
+
+// Simplifies the predicates of `where`, which keeps rows for which all are
+// true: `true` is dropped, `age < 3 == true` is `age < 3` and
+// `age < 3 == false` is `age >= 3`. Rows are checked against predicates in
+// order, so those after a constant which isn't true are never evaluated.
+pub fn predicates(predicates: Vec<AST>) -> Vec<AST> {
+    let mut simplified = vec![];
+    for predicate in predicates {
+        match truth(fold(predicate)) {
+            AST::Bool(true) => {}
+            predicate if constant(&predicate) => {
+                simplified.push(AST::Bool(false));
+                break;
+            }
+            predicate => simplified.push(predicate),
+        }
+    }
+    simplified
+}
+
+fn truth(predicate: AST) -> AST {
+    if let AST::BinOP(left, OP::EqEq, right) = predicate {
+        return match (*left, *right) {
+            (AST::BinOP(l, op, r), AST::Bool(true)) if comparison(&op) => AST::BinOP(l, op, r),
+            (AST::BinOP(l, op, r), AST::Bool(false)) if comparison(&op) => {
+                AST::BinOP(l, op.negate().unwrap(), r)
+            }
+            (left, right) => AST::binop(left, OP::EqEq, right),
+        };
+    }
+    predicate
+}
+
+fn constant(tree: &AST) -> bool {
+    matches!(
+        tree,
+        AST::Number(_) | AST::StrLiteral(_) | AST::Bool(_) | AST::Null
+    )
+}
+
+fn comparison(op: &OP) -> bool {
+    op.negate().is_some()
+}
+
+fn evaluate(node: AST) -> AST {
+    match Value::eval_row(&node, &[], &[]) {
+        Ok(value) => value.into(),
+        Err(_) => node,
+    }
+}
+
+#[cfg(test)]
+use crate::{ast::Member, parser::run, primitive::Type};
+
+#[cfg(test)]
+fn exprs(src: &str) -> Vec<AST> {
+    match run(&format!("T.select({})", src)) {
+        AST::MethodCall { args, .. } => args,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+fn rows() -> (Vec<Member>, Vec<Vec<Value>>) {
+    let members = vec![
+        Member::new("age", Type::Int),
+        Member::new("name", Type::StrLiteral),
+    ];
+    let rows = vec![
+        vec![Value::Int(1), Value::StrLiteral("kuru".to_string())],
+        vec![Value::Int(5), Value::StrLiteral("knium".to_string())],
+        vec![Value::Null, Value::Null],
+    ];
+    (members, rows)
+}
+
+#[test]
+fn folding() {
+    let folded = |src| {
+        let folded: Vec<String> = exprs(src)
+            .into_iter()
+            .map(|e| fold(e).to_string())
+            .collect();
+        folded.join(", ")
+    };
+    assert_eq!(
+        folded("1 + 2 * 3, age > 1 + 2, 3 < age"),
+        "7, age > 3, age > 3"
+    );
+    assert_eq!(
+        folded("upper(\"a\"), len(name) == 2 - 1, 1 - age, 1 / 0, 2147483647 + 1"),
+        "\"A\", len(name) == 1, 1 - age, 1 / 0, 2147483647 + 1"
+    );
+    assert_eq!(
+        folded("if(1 < 2, name, \"?\"), if(null, 1, age), if(true, age, name)"),
+        "name, age, if(true, age, name)"
+    );
+    assert_eq!(folded("n: coalesce(null, 2) + age"), "n: 2 + age");
+
+    // Every row gets the same result, or error, from the folded expression.
+    let (members, rows) = rows();
+    let src = "1 + 2 * 3, age + 2 * 3, 3 <= age, 2 == age - 3, null < age, \
+               concat(name, upper(\"!\")), if(false, 1, age), 1 / 0 + age, name > 1 + 1, \
+               age * 2147483647, 0 - 2147483647 - age";
+    for expr in exprs(src) {
+        for row in &rows {
+            assert_eq!(
+                Value::eval_row(&fold(expr.clone()), &members, row),
+                Value::eval_row(&expr, &members, row),
+                "{}",
+                expr
+            );
+        }
+    }
+}
+
+#[test]
+fn simplification() {
+    let simplified = |src| {
+        let simplified: Vec<String> = predicates(exprs(src))
+            .into_iter()
+            .map(|p| p.to_string())
+            .collect();
+        simplified.join(", ")
+    };
+    assert_eq!(simplified("true, 1 < 2, age > 2"), "age > 2");
+    assert_eq!(
+        simplified("age < 3 == true, true == 3 > age, age < 3 == false"),
+        "age < 3, age < 3, age >= 3"
+    );
+    assert_eq!(
+        simplified("name == \"a\", 1 > 2, age"),
+        "name == \"a\", false"
+    );
+    assert_eq!(
+        simplified("age == 2 == false, age + 1 == false"),
+        "age != 2, (age + 1) == false"
+    );
+
+    // The same rows pass the simplified predicates, or fail the same way.
+    let (members, rows) = rows();
+    let keeps = |predicates: &[AST], row: &[Value]| -> Result<bool, String> {
+        for predicate in predicates {
+            if Value::eval_row(predicate, &members, row)? != Value::Bool(true) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    };
+    for src in [
+        "age < 3 == false",
+        "age >= 5 == true, 2 > 1",
+        "age == 1 == false, name != \"x\"",
+        "age > 0, null, unknown",
+        "1 + 1 == 2, name < \"l\" == false",
+    ] {
+        let original = exprs(src);
+        let simplified = predicates(original.clone());
+        for row in &rows {
+            assert_eq!(keeps(&simplified, row), keeps(&original, row), "{}", src);
+        }
+    }
+}
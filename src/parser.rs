@@ -1,5 +1,5 @@
 use self::AST::*;
-use crate::ast::{Member, AST, OP};
+use crate::ast::{Constraint, Event, Member, Method, OnDelete, Timing, Trigger, AST, OP};
 use crate::primitive::Type;
 use crate::token::{KeywordKind, Token};
 use std::collections::VecDeque;
@@ -9,104 +9,165 @@ pub struct Parser {
     tokens: VecDeque<Token>,
 }
 
+type ParseResult<T> = Result<T, String>;
+
 macro_rules! expect {
     ($self: ident, $variant: ident, $value: expr) => {
-        let t = $self.get();
-        if let Token::$variant($value) = t {
-            ()
-        } else {
-            panic!("Unexpected token! {:?}", t)
+        let t = $self.get()?;
+        if t != Token::$variant($value) {
+            return Err(unexpected(&t));
         }
     };
 }
 
 macro_rules! get {
     ($self: ident, $variant: ident) => {
-        if let Token::$variant(value) = $self.get() {
-            value
-        } else {
-            unreachable!()
+        match $self.get()? {
+            Token::$variant(value) => value,
+            t => return Err(unexpected(&t)),
         }
     };
 }
 
 macro_rules! def_parse_binop {
-    ($name: ident, $one: ident, $another: ident, $next: ident) => {
-        fn $name(&mut self) -> AST {
-        let mut left = self.$next();
-        loop {
-            match self.peek() {
-                Some(&Token::$one) | Some(&Token::$another) => (),
-                _ => break
+    ($name: ident, [$($token: ident),+], $next: ident) => {
+        fn $name(&mut self) -> ParseResult<AST> {
+            let mut left = self.$next()?;
+            loop {
+                match self.peek() {
+                    $(Some(&Token::$token))|+ => (),
+                    _ => break,
+                }
+                let op = self.get()?;
+                let right = self.$next()?;
+                left = AST::binop(left, OP::from_token(op), right);
             }
-            let op = self.get();
-            let right = self.$next();
-            left = AST::binop(left, OP::from_token(op), right);
+            Ok(left)
         }
-        left
-    }
     };
 }
 
+fn unexpected(t: &Token) -> String {
+    format!("Unexpected token {:?}!!", t)
+}
+
 impl Parser {
     pub fn new(tokens: VecDeque<Token>) -> Self {
         Self { index: 0, tokens }
     }
 
-    fn term(&mut self) -> AST {
-        let t = self.get();
-        match t {
+    fn term(&mut self) -> ParseResult<AST> {
+        let t = self.get()?;
+        Ok(match t {
             Token::Number(n) => Number(n),
+            // Unary minus, as in `-2` or `-age`.
+            Token::Minus => match self.term()? {
+                Number(n) => Number(-n),
+                term => AST::binop(Number(0), OP::Minus, term),
+            },
             Token::StrLiteral(s) => StrLiteral(s),
-            _ => {
-                dbg!(t);
-                dbg!(&self.tokens);
-                panic!();
+            Token::Ident(s) if s == "nextval" && self.peek() == Some(&Token::Symbol('(')) => {
+                self.get()?;
+                let sequence = get!(self, Ident);
+                expect!(self, Symbol, ')');
+                NextVal(sequence)
             }
-        }
+            Token::Ident(s) if self.peek() == Some(&Token::Symbol('(')) => Call(s, self.args()?),
+            Token::Keyword(KeywordKind::Int) => Call("int".to_string(), self.args()?),
+            Token::Keyword(KeywordKind::StrLiteral) => Call("string".to_string(), self.args()?),
+            Token::Keyword(KeywordKind::Null) => Null,
+            Token::Param(param) => Param(param),
+            Token::Ident(s) if self.peek() == Some(&Token::Symbol('.')) => {
+                self.get()?;
+                Field(s, get!(self, Ident))
+            }
+            Token::Ident(s) => Ident(s),
+            Token::Keyword(KeywordKind::True) => Bool(true),
+            Token::Keyword(KeywordKind::False) => Bool(false),
+            t => return Err(unexpected(&t)),
+        })
     }
 
-    def_parse_binop!(mul, Mul, Slash, term);
-    def_parse_binop!(add, Add, Minus, mul);
+    def_parse_binop!(mul, [Mul, Slash], term);
+    def_parse_binop!(add, [Add, Minus], mul);
+    def_parse_binop!(relational, [Lt, LtEq, Gt, GtEq], add);
+    def_parse_binop!(equal, [EqEq, NotEq], relational);
 
-    fn equal(&mut self) -> AST {
-        let mut left = self.add();
-        loop {
-            if self.peek() != Some(&Token::EqEq) {
-                break;
-            }
-            // TODO: Fix hard coding.
-            self.get();
-            let right = self.add();
-            left = AST::binop(left, OP::EqEq, right);
-        }
-        left
+    fn expr(&mut self) -> ParseResult<AST> {
+        self.equal()
     }
 
-    fn expr(&mut self) -> AST {
-        self.equal()
+    // An argument, optionally labelled as in `header: true`.
+    fn arg(&mut self) -> ParseResult<AST> {
+        if let (Some(Token::Ident(_)), Some(Token::Symbol(':'))) =
+            (self.peek(), self.tokens.get(self.index + 1))
+        {
+            let field = get!(self, Ident);
+            self.get()?;
+            if let Some(Token::Keyword(KeywordKind::Int))
+            | Some(Token::Keyword(KeywordKind::StrLiteral)) = self.peek()
+            {
+                let typ = self.typ()?;
+                let default = match self.peek() {
+                    Some(Token::Symbol('=')) => {
+                        self.get()?;
+                        Some(Box::new(self.expr()?))
+                    }
+                    _ => None,
+                };
+                return Ok(AST::Column(Member::new(&field, typ), default));
+            }
+            return Ok(AST::named_arg(field, self.expr()?));
+        }
+        self.expr()
     }
 
-    fn method_call(&mut self) -> AST {
-        let table = get!(self, Ident);
-        expect!(self, Symbol, '.');
-        let name = get!(self, Ident);
+    fn args(&mut self) -> ParseResult<Vec<AST>> {
         expect!(self, Symbol, '(');
         let args = {
             if let Some(&Token::Symbol(')')) = self.peek() {
                 vec![]
             } else {
                 let mut v = vec![];
-                v.push(self.expr());
+                v.push(self.arg()?);
                 while let Some(&Token::Symbol(',')) = self.peek() {
-                    self.get();
-                    v.push(self.expr());
+                    self.get()?;
+                    v.push(self.arg()?);
                 }
                 v
             }
         };
         expect!(self, Symbol, ')');
-        AST::MethodCall { table, name, args }
+        Ok(args)
+    }
+
+    fn method_call(&mut self) -> ParseResult<AST> {
+        let table = get!(self, Ident);
+        expect!(self, Symbol, '.');
+        self.calls(table)
+    }
+
+    // `name(args).name(args)...` called on `table`.
+    fn calls(&mut self, table: String) -> ParseResult<AST> {
+        let name = get!(self, Ident);
+        let args = self.args()?;
+        let mut tree = AST::MethodCall { table, name, args };
+        while let Some(&Token::Symbol('.')) = self.peek() {
+            self.get()?;
+            let name = get!(self, Ident);
+            let args = self.args()?;
+            tree = AST::chain(tree, name, args);
+        }
+        Ok(tree)
+    }
+
+    // A member type, `int` or `string`.
+    fn typ(&mut self) -> ParseResult<Type> {
+        match self.get()? {
+            Token::Keyword(KeywordKind::Int) => Ok(Type::Int),
+            Token::Keyword(KeywordKind::StrLiteral) => Ok(Type::StrLiteral),
+            t => Err(unexpected(&t)),
+        }
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -117,50 +178,263 @@ impl Parser {
         }
     }
 
-    fn get(&mut self) -> Token {
-        if let Some(t) = self.tokens.pop_front() {
-            t
-        } else {
-            panic!();
+    fn get(&mut self) -> ParseResult<Token> {
+        self.tokens
+            .pop_front()
+            .ok_or_else(|| "Unexpected end of input!!".to_string())
+    }
+
+    // Constraints following a member's type, e.g. `references User(id) cascade`.
+    fn constraints(&mut self) -> ParseResult<Vec<Constraint>> {
+        let mut constraints = vec![];
+        while let Some(Token::Ident(word)) = self.peek() {
+            match &**word {
+                "references" => {
+                    self.get()?;
+                    let table = get!(self, Ident);
+                    expect!(self, Symbol, '(');
+                    let field = get!(self, Ident);
+                    expect!(self, Symbol, ')');
+                    let on_delete = match self.peek() {
+                        Some(Token::Ident(word)) if word == "cascade" => {
+                            self.get()?;
+                            OnDelete::Cascade
+                        }
+                        Some(Token::Ident(word)) if word == "restrict" => {
+                            self.get()?;
+                            OnDelete::Restrict
+                        }
+                        _ => OnDelete::Restrict,
+                    };
+                    constraints.push(Constraint::References {
+                        table,
+                        field,
+                        on_delete,
+                    });
+                }
+                "check" => {
+                    self.get()?;
+                    constraints.push(Constraint::Check(self.check()?));
+                }
+                "auto" => {
+                    self.get()?;
+                    constraints.push(Constraint::Auto);
+                }
+                _ => return Err(format!("Unexpected constraint {:?}!!", word)),
+            }
         }
+        Ok(constraints)
+    }
+
+    // The parenthesized expression of `check(...)`.
+    fn check(&mut self) -> ParseResult<AST> {
+        expect!(self, Symbol, '(');
+        let expr = self.expr()?;
+        expect!(self, Symbol, ')');
+        Ok(expr)
     }
 
-    fn table_def(&mut self) -> AST {
-        self.get();
+    fn table_def(&mut self) -> ParseResult<AST> {
+        self.get()?;
+        let modifier = match (self.peek(), self.tokens.get(self.index + 1)) {
+            (Some(Token::Ident(_)), Some(Token::Ident(_))) => Some(get!(self, Ident)),
+            _ => None,
+        };
         let name = get!(self, Ident);
         expect!(self, Symbol, '{');
+        let mut checks = vec![];
         let members = if let Some(&Token::Symbol('}')) = self.peek() {
             vec![]
         } else {
             let mut v = vec![];
             loop {
                 match self.peek() {
+                    Some(Token::Ident(word))
+                        if word == "check"
+                            && self.tokens.get(self.index + 1) == Some(&Token::Symbol('(')) =>
+                    {
+                        self.get()?;
+                        checks.push(self.check()?);
+                    }
                     Some(Token::Ident(_)) => {
                         let field = get!(self, Ident);
                         expect!(self, Symbol, ':');
-                        let typ = Type::from_token(self.get());
-                        v.push(Member { typ, field });
+                        let typ = self.typ()?;
+                        let constraints = self.constraints()?;
+                        v.push(Member {
+                            typ,
+                            field,
+                            constraints,
+                        });
                     }
                     Some(Token::Symbol(',')) => {
-                        self.get();
+                        self.get()?;
                         continue;
                     }
                     Some(Token::Symbol('}')) => break,
-                    _ => unreachable!(),
+                    _ => return Err(unexpected(&self.get()?)),
                 }
             }
             v
         };
         expect!(self, Symbol, '}');
-        AST::TableDef { name, members }
+        let def = AST::TableDef {
+            name,
+            members,
+            checks,
+        };
+        match modifier.as_deref() {
+            Some("ifNotExists") => Ok(AST::IfNotExists(Box::new(def))),
+            Some("replace") => Ok(AST::Replace(Box::new(def))),
+            Some(modifier) => Err(format!("Unexpected modifier {:?}!!", modifier)),
+            None => Ok(def),
+        }
+    }
+
+    fn view_def(&mut self) -> ParseResult<AST> {
+        self.get()?;
+        let mut modifiers = vec![];
+        while self.tokens.get(self.index + 1) != Some(&Token::Symbol('=')) {
+            modifiers.push(get!(self, Ident));
+        }
+        let name = get!(self, Ident);
+        expect!(self, Symbol, '=');
+        let query = Box::new(self.method_call()?);
+        let view = AST::ViewDef { name, query };
+        match modifiers
+            .iter()
+            .map(|m| &**m)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [] => Ok(view),
+            ["materialized"] => Ok(AST::Materialized {
+                view: Box::new(view),
+                auto_refresh: false,
+            }),
+            ["materialized", "autoRefresh"] => Ok(AST::Materialized {
+                view: Box::new(view),
+                auto_refresh: true,
+            }),
+            modifiers => Err(format!("Unexpected modifier {:?}!!", modifiers)),
+        }
     }
 
-    pub fn parse(&mut self) -> AST {
-        match self.peek().unwrap() {
+    fn trigger_def(&mut self) -> ParseResult<AST> {
+        self.get()?;
+        let name = get!(self, Ident);
+        let timing = match &*get!(self, Ident) {
+            "before" => Timing::Before,
+            "after" => Timing::After,
+            timing => return Err(format!("Unexpected timing {:?}!!", timing)),
+        };
+        let event = match &*get!(self, Ident) {
+            "insert" => Event::Insert,
+            "update" => Event::Update,
+            "delete" => Event::Delete,
+            event => return Err(format!("Unexpected event {:?}!!", event)),
+        };
+        let on = get!(self, Ident);
+        if on != "on" {
+            return Err(unexpected(&Token::Ident(on)));
+        }
+        let table = get!(self, Ident);
+        expect!(self, Symbol, '=');
+        let action = self.method_call()?;
+        Ok(AST::TriggerDef {
+            table,
+            trigger: Box::new(Trigger {
+                name,
+                timing,
+                event,
+                action,
+            }),
+        })
+    }
+
+    fn method_def(&mut self) -> ParseResult<AST> {
+        self.get()?;
+        let table = get!(self, Ident);
+        expect!(self, Symbol, '.');
+        let name = get!(self, Ident);
+        let params = self
+            .args()?
+            .into_iter()
+            .map(|param| match param {
+                AST::Column(member, None) => Ok(member),
+                param => Err(format!("Unexpected parameter {}!!", param)),
+            })
+            .collect::<ParseResult<_>>()?;
+        expect!(self, Symbol, '=');
+        let body = self.calls(table.clone())?;
+        Ok(AST::MethodDef {
+            table,
+            method: Box::new(Method { name, params, body }),
+        })
+    }
+
+    // A whole statement, or an error naming what couldn't be parsed.
+    pub fn parse(&mut self) -> ParseResult<AST> {
+        let t = self
+            .peek()
+            .ok_or_else(|| "Unexpected end of input!!".to_string())?;
+        match t {
             Token::Number(_) => self.expr(),
             Token::Ident(_) => self.method_call(),
             Token::Keyword(KeywordKind::Table) => self.table_def(),
-            _ => unimplemented!(),
+            Token::Keyword(KeywordKind::View) => self.view_def(),
+            Token::Keyword(KeywordKind::Trigger) => self.trigger_def(),
+            Token::Keyword(KeywordKind::Method) => self.method_def(),
+            Token::Keyword(KeywordKind::Begin) => {
+                self.get()?;
+                Ok(AST::Begin)
+            }
+            Token::Keyword(KeywordKind::Commit) => {
+                self.get()?;
+                Ok(AST::Commit)
+            }
+            Token::Keyword(KeywordKind::Rollback) => {
+                self.get()?;
+                match self.peek() {
+                    Some(Token::Ident(to)) if to == "to" => {
+                        self.get()?;
+                        Ok(AST::RollbackTo(get!(self, Ident)))
+                    }
+                    _ => Ok(AST::Rollback),
+                }
+            }
+            Token::Keyword(KeywordKind::Explain) => {
+                self.get()?;
+                Ok(AST::Explain(Box::new(self.method_call()?)))
+            }
+            Token::Keyword(KeywordKind::Analyze) => {
+                self.get()?;
+                match self.peek() {
+                    Some(Token::Ident(_)) => Ok(AST::Analyze(Some(get!(self, Ident)))),
+                    _ => Ok(AST::Analyze(None)),
+                }
+            }
+            Token::Keyword(KeywordKind::Tables) => {
+                self.get()?;
+                Ok(AST::Tables)
+            }
+            Token::Keyword(KeywordKind::Drop) => {
+                self.get()?;
+                Ok(AST::Drop(get!(self, Ident)))
+            }
+            Token::Keyword(KeywordKind::Sequence) => {
+                self.get()?;
+                Ok(AST::Sequence(get!(self, Ident)))
+            }
+            Token::Keyword(KeywordKind::Savepoint) => {
+                self.get()?;
+                Ok(AST::Savepoint(get!(self, Ident)))
+            }
+            Token::Keyword(KeywordKind::Release) => {
+                self.get()?;
+                Ok(AST::Release(get!(self, Ident)))
+            }
+            t => Err(unexpected(t)),
         }
     }
 }
@@ -168,24 +442,45 @@ impl Parser {
 #[allow(unused_imports)]
 use crate::tokenizer::Tokenizer;
 
+// Parses `src`, for tests across the crate.
+#[cfg(test)]
+pub fn run(src: &str) -> AST {
+    Parser::new(Tokenizer::new(src).lex_all().unwrap())
+        .parse()
+        .unwrap()
+}
+
 #[test]
 fn new() {
-    let tokens = Tokenizer::new("hogefuga").lex_all();
+    let tokens = Tokenizer::new("hogefuga").lex_all().unwrap();
     assert_eq!(Parser::new(tokens).index, 0);
 }
 
 #[test]
 fn term() {
     let s = "\"kuru\"";
-    let tokens = Tokenizer::new(&s).lex_all();
-    assert_eq!(Parser::new(tokens).term(), StrLiteral("kuru".to_string()));
+    let tokens = Tokenizer::new(s).lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).term().unwrap(),
+        StrLiteral("kuru".to_string())
+    );
+
+    let tokens = Tokenizer::new("round(1250, -2) - -age").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).expr().unwrap(),
+        AST::binop(
+            Call("round".to_string(), vec![Number(1250), Number(-2)]),
+            OP::Minus,
+            AST::binop(Number(0), OP::Minus, Ident("age".to_string()))
+        )
+    );
 }
 
 #[test]
 fn add() {
-    let tokens = Tokenizer::new("1 + 2 + 3").lex_all();
+    let tokens = Tokenizer::new("1 + 2 + 3").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).add(),
+        Parser::new(tokens).add().unwrap(),
         AST::binop(
             AST::binop(Number(1), OP::Add, Number(2)),
             OP::Add,
@@ -196,9 +491,9 @@ fn add() {
 
 #[test]
 fn mul() {
-    let tokens = Tokenizer::new("1 + 2 * 3").lex_all();
+    let tokens = Tokenizer::new("1 + 2 * 3").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).add(),
+        Parser::new(tokens).add().unwrap(),
         AST::binop(
             Number(1),
             OP::Add,
@@ -206,9 +501,9 @@ fn mul() {
         )
     );
 
-    let tokens = Tokenizer::new("1 + 2 / 3").lex_all();
+    let tokens = Tokenizer::new("1 + 2 / 3").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).add(),
+        Parser::new(tokens).add().unwrap(),
         AST::binop(
             Number(1),
             OP::Add,
@@ -219,9 +514,9 @@ fn mul() {
 
 #[test]
 fn equal() {
-    let tokens = Tokenizer::new("4 == 2 + 3").lex_all();
+    let tokens = Tokenizer::new("4 == 2 + 3").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).equal(),
+        Parser::new(tokens).equal().unwrap(),
         AST::binop(
             Number(4),
             OP::EqEq,
@@ -232,9 +527,9 @@ fn equal() {
 
 #[test]
 fn method_call() {
-    let tokens = Tokenizer::new("User.select()").lex_all();
+    let tokens = Tokenizer::new("User.select()").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).method_call(),
+        Parser::new(tokens).method_call().unwrap(),
         AST::MethodCall {
             table: "User".to_string(),
             name: "select".to_string(),
@@ -242,9 +537,9 @@ fn method_call() {
         }
     );
 
-    let tokens = Tokenizer::new("User.select(1)").lex_all();
+    let tokens = Tokenizer::new("User.select(1)").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).method_call(),
+        Parser::new(tokens).method_call().unwrap(),
         AST::MethodCall {
             table: "User".to_string(),
             name: "select".to_string(),
@@ -252,9 +547,9 @@ fn method_call() {
         }
     );
 
-    let tokens = Tokenizer::new("User.select(1+1, 2)").lex_all();
+    let tokens = Tokenizer::new("User.select(1+1, 2)").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).method_call(),
+        Parser::new(tokens).method_call().unwrap(),
         AST::MethodCall {
             table: "User".to_string(),
             name: "select".to_string(),
@@ -263,62 +558,416 @@ fn method_call() {
     );
 }
 
+#[test]
+fn named_arg() {
+    let tokens = Tokenizer::new("User.importCsv(\"users.csv\", header: true)")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).method_call().unwrap(),
+        AST::MethodCall {
+            table: "User".to_string(),
+            name: "importCsv".to_string(),
+            args: vec![
+                StrLiteral("users.csv".to_string()),
+                AST::named_arg("header".to_string(), Bool(true))
+            ]
+        }
+    );
+}
+
+#[test]
+fn column() {
+    let tokens = Tokenizer::new("User.addColumn(age: int = 0)")
+        .lex_all()
+        .unwrap();
+    let member = Member::new("age", Type::Int);
+    assert_eq!(
+        Parser::new(tokens).method_call().unwrap(),
+        AST::MethodCall {
+            table: "User".to_string(),
+            name: "addColumn".to_string(),
+            args: vec![AST::Column(member.clone(), Some(Box::new(Number(0))))]
+        }
+    );
+
+    let tokens = Tokenizer::new("User.addColumn(age: int)")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).method_call().unwrap(),
+        AST::MethodCall {
+            table: "User".to_string(),
+            name: "addColumn".to_string(),
+            args: vec![AST::Column(member, None)]
+        }
+    );
+}
+
+#[test]
+fn chain() {
+    let tokens = Tokenizer::new("User.select().where(id == 3)")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).method_call().unwrap(),
+        AST::chain(
+            AST::MethodCall {
+                table: "User".to_string(),
+                name: "select".to_string(),
+                args: vec![]
+            },
+            "where".to_string(),
+            vec![AST::binop(Ident("id".to_string()), OP::EqEq, Number(3))]
+        )
+    );
+}
+
+#[test]
+fn transaction() {
+    let tokens = Tokenizer::new("begin;").lex_all().unwrap();
+    assert_eq!(Parser::new(tokens).parse().unwrap(), AST::Begin);
+
+    let tokens = Tokenizer::new("commit;").lex_all().unwrap();
+    assert_eq!(Parser::new(tokens).parse().unwrap(), AST::Commit);
+
+    let tokens = Tokenizer::new("rollback;").lex_all().unwrap();
+    assert_eq!(Parser::new(tokens).parse().unwrap(), AST::Rollback);
+}
+
+#[test]
+fn savepoint() {
+    let tokens = Tokenizer::new("savepoint fix;").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::Savepoint("fix".to_string())
+    );
+
+    let tokens = Tokenizer::new("rollback to fix;").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::RollbackTo("fix".to_string())
+    );
+
+    let tokens = Tokenizer::new("release fix;").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::Release("fix".to_string())
+    );
+}
+
 #[test]
 fn table_def() {
-    let tokens = Tokenizer::new("Table NewUser {}").lex_all();
+    let tokens = Tokenizer::new("Table NewUser {}").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).table_def(),
+        Parser::new(tokens).table_def().unwrap(),
         AST::TableDef {
             name: "NewUser".to_string(),
-            members: vec![]
+            members: vec![],
+            checks: vec![],
         }
     );
 
-    let tokens = Tokenizer::new("Table NewUser {id: int}").lex_all();
+    let tokens = Tokenizer::new("Table NewUser {id: int}").lex_all().unwrap();
     assert_eq!(
-        Parser::new(tokens).table_def(),
+        Parser::new(tokens).table_def().unwrap(),
         AST::TableDef {
             name: "NewUser".to_string(),
-            members: vec![Member {
-                typ: Type::Int,
-                field: "id".to_string(),
-            }]
+            members: vec![Member::new("id", Type::Int)],
+            checks: vec![],
         }
     );
 
-    let tokens = Tokenizer::new("Table NewUser {id: int, name: string,}").lex_all();
+    let tokens = Tokenizer::new("Table NewUser {id: int, name: string,}")
+        .lex_all()
+        .unwrap();
     assert_eq!(
-        Parser::new(tokens).table_def(),
+        Parser::new(tokens).table_def().unwrap(),
         AST::TableDef {
             name: "NewUser".to_string(),
             members: vec![
-                Member {
-                    typ: Type::Int,
-                    field: "id".to_string(),
-                },
-                Member {
-                    typ: Type::StrLiteral,
-                    field: "name".to_string(),
-                }
-            ]
+                Member::new("id", Type::Int),
+                Member::new("name", Type::StrLiteral)
+            ],
+            checks: vec![],
         }
     );
 
-    let tokens = Tokenizer::new("Table NewUser {id: int, name: string}").lex_all();
+    let tokens = Tokenizer::new("Table NewUser {id: int, name: string}")
+        .lex_all()
+        .unwrap();
     assert_eq!(
-        Parser::new(tokens).table_def(),
+        Parser::new(tokens).table_def().unwrap(),
         AST::TableDef {
             name: "NewUser".to_string(),
             members: vec![
-                Member {
-                    typ: Type::Int,
-                    field: "id".to_string(),
+                Member::new("id", Type::Int),
+                Member::new("name", Type::StrLiteral)
+            ],
+            checks: vec![],
+        }
+    );
+}
+
+#[test]
+fn table_lifecycle() {
+    let def = AST::TableDef {
+        name: "NewUser".to_string(),
+        members: vec![],
+        checks: vec![],
+    };
+    let tokens = Tokenizer::new("Table ifNotExists NewUser {}")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::IfNotExists(Box::new(def.clone()))
+    );
+
+    let tokens = Tokenizer::new("Table replace NewUser {}")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::Replace(Box::new(def))
+    );
+
+    let tokens = Tokenizer::new("tables;").lex_all().unwrap();
+    assert_eq!(Parser::new(tokens).parse().unwrap(), AST::Tables);
+
+    let tokens = Tokenizer::new("drop NewUser;").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::Drop("NewUser".to_string())
+    );
+}
+
+#[test]
+fn references() {
+    let tokens = Tokenizer::new(
+        "Table Order {user_id: int references User(id) cascade, item_id: int references Item(id)}",
+    )
+    .lex_all()
+    .unwrap();
+    let mut user_id = Member::new("user_id", Type::Int);
+    user_id.constraints.push(Constraint::References {
+        table: "User".to_string(),
+        field: "id".to_string(),
+        on_delete: OnDelete::Cascade,
+    });
+    let mut item_id = Member::new("item_id", Type::Int);
+    item_id.constraints.push(Constraint::References {
+        table: "Item".to_string(),
+        field: "id".to_string(),
+        on_delete: OnDelete::Restrict,
+    });
+    assert_eq!(
+        Parser::new(tokens).table_def().unwrap(),
+        AST::TableDef {
+            name: "Order".to_string(),
+            members: vec![user_id, item_id],
+            checks: vec![],
+        }
+    );
+}
+
+#[test]
+fn check() {
+    let tokens =
+        Tokenizer::new("Table User {age: int check(age >= 0), limit: int, check(age < limit)}")
+            .lex_all()
+            .unwrap();
+    let mut age = Member::new("age", Type::Int);
+    age.constraints.push(Constraint::Check(AST::binop(
+        Ident("age".to_string()),
+        OP::GtEq,
+        Number(0),
+    )));
+    assert_eq!(
+        Parser::new(tokens).table_def().unwrap(),
+        AST::TableDef {
+            name: "User".to_string(),
+            members: vec![age, Member::new("limit", Type::Int)],
+            checks: vec![AST::binop(
+                Ident("age".to_string()),
+                OP::Lt,
+                Ident("limit".to_string())
+            )],
+        }
+    );
+
+    let tokens = Tokenizer::new("1 + 2 < 4 != false").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).expr().unwrap(),
+        AST::binop(
+            AST::binop(AST::binop(Number(1), OP::Add, Number(2)), OP::Lt, Number(4)),
+            OP::NotEq,
+            Bool(false)
+        )
+    );
+}
+
+#[test]
+fn sequence() {
+    let tokens = Tokenizer::new("Table Order {id: int auto, total: int}")
+        .lex_all()
+        .unwrap();
+    let mut id = Member::new("id", Type::Int);
+    id.constraints.push(Constraint::Auto);
+    assert_eq!(
+        Parser::new(tokens).table_def().unwrap(),
+        AST::TableDef {
+            name: "Order".to_string(),
+            members: vec![id, Member::new("total", Type::Int)],
+            checks: vec![],
+        }
+    );
+
+    let tokens = Tokenizer::new("sequence order_seq;").lex_all().unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::Sequence("order_seq".to_string())
+    );
+
+    let tokens = Tokenizer::new("Order.insert(nextval(order_seq) * 10, 3)")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::MethodCall {
+            table: "Order".to_string(),
+            name: "insert".to_string(),
+            args: vec![
+                AST::binop(NextVal("order_seq".to_string()), OP::Mul, Number(10)),
+                Number(3)
+            ],
+        }
+    );
+}
+
+#[test]
+fn view() {
+    let tokens = Tokenizer::new("View Adults = User.select().where(age >= 18);")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::ViewDef {
+            name: "Adults".to_string(),
+            query: Box::new(AST::chain(
+                AST::MethodCall {
+                    table: "User".to_string(),
+                    name: "select".to_string(),
+                    args: vec![],
                 },
-                Member {
-                    typ: Type::StrLiteral,
-                    field: "name".to_string(),
-                }
-            ]
+                "where".to_string(),
+                vec![AST::binop(Ident("age".to_string()), OP::GtEq, Number(18))]
+            )),
         }
     );
 }
+
+#[test]
+fn materialized() {
+    let tokens = Tokenizer::new("View materialized autoRefresh Counts = User.select()")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::Materialized {
+            view: Box::new(AST::ViewDef {
+                name: "Counts".to_string(),
+                query: Box::new(AST::MethodCall {
+                    table: "User".to_string(),
+                    name: "select".to_string(),
+                    args: vec![],
+                }),
+            }),
+            auto_refresh: true,
+        }
+    );
+}
+
+#[test]
+fn trigger() {
+    let tokens =
+        Tokenizer::new("trigger audit after update on User = Log.insert(old.age, new.age + 1);")
+            .lex_all()
+            .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::TriggerDef {
+            table: "User".to_string(),
+            trigger: Box::new(Trigger {
+                name: "audit".to_string(),
+                timing: Timing::After,
+                event: Event::Update,
+                action: AST::MethodCall {
+                    table: "Log".to_string(),
+                    name: "insert".to_string(),
+                    args: vec![
+                        Field("old".to_string(), "age".to_string()),
+                        AST::binop(
+                            Field("new".to_string(), "age".to_string()),
+                            OP::Add,
+                            Number(1)
+                        ),
+                    ],
+                },
+            }),
+        }
+    );
+}
+
+#[test]
+fn method() {
+    let tokens = Tokenizer::new("method User.adults(minAge: int) = select().where(age >= minAge);")
+        .lex_all()
+        .unwrap();
+    assert_eq!(
+        Parser::new(tokens).parse().unwrap(),
+        AST::MethodDef {
+            table: "User".to_string(),
+            method: Box::new(Method {
+                name: "adults".to_string(),
+                params: vec![Member::new("minAge", Type::Int)],
+                body: AST::chain(
+                    AST::MethodCall {
+                        table: "User".to_string(),
+                        name: "select".to_string(),
+                        args: vec![],
+                    },
+                    "where".to_string(),
+                    vec![AST::binop(
+                        Ident("age".to_string()),
+                        OP::GtEq,
+                        Ident("minAge".to_string())
+                    )]
+                ),
+            }),
+        }
+    );
+}
+
+#[test]
+fn call() {
+    let tokens = Tokenizer::new("if(len(name) > 3, upper(name), int(\"0\")) == null")
+        .lex_all()
+        .unwrap();
+    let name = || Ident("name".to_string());
+    assert_eq!(
+        Parser::new(tokens).expr().unwrap(),
+        AST::binop(
+            Call(
+                "if".to_string(),
+                vec![
+                    AST::binop(Call("len".to_string(), vec![name()]), OP::Gt, Number(3)),
+                    Call("upper".to_string(), vec![name()]),
+                    Call("int".to_string(), vec![StrLiteral("0".to_string())]),
+                ]
+            ),
+            OP::EqEq,
+            Null
+        )
+    );
+}
@@ -0,0 +1,1066 @@
+use crate::ast::{Member, AST, OP};
+use crate::database::Database;
+use crate::function;
+use crate::optimize;
+use crate::primitive::Type;
+use crate::statistics::Statistics;
+use crate::table::{column, qualified, Value, Values, Version};
+use crate::vm::Program;
+use std::fmt;
+
+type Rows = (Vec<Member>, Vec<Values>);
+
+// Reading a row through an index costs this many rows read by a scan.
+const RANDOM_ACCESS: f64 = 4.0;
+// Rows assumed in a table which was never analyzed.
+const DEFAULT_ROWS: f64 = 1000.0;
+// Joins of up to this many tables are planned in every order.
+const MAX_REORDERED: usize = 6;
+
+// The rows a plan is expected to read and return, in rows read by a scan.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Estimate {
+    pub cost: f64,
+    pub rows: f64,
+}
+
+// What a `select` query computes, in the order the methods are applied:
+// joins, then filters, then sorting, then the selected expressions.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Logical {
+    Scan(String),
+    Join {
+        left: Box<Logical>,
+        right: Box<Logical>,
+        on: AST,
+    },
+    Filter {
+        input: Box<Logical>,
+        predicates: Vec<AST>,
+    },
+    Sort {
+        input: Box<Logical>,
+        key: AST,
+        descending: bool,
+    },
+    Project {
+        input: Box<Logical>,
+        exprs: Vec<AST>,
+    },
+    Aggregate {
+        input: Box<Logical>,
+        exprs: Vec<AST>,
+    },
+}
+
+impl Logical {
+    // Whether `tree` is a `select` query the planner understands.
+    pub fn selects(tree: &AST) -> bool {
+        match tree {
+            AST::Chain { receiver, name, .. } => {
+                matches!(&**name, "where" | "orderBy" | "join") && Self::selects(receiver)
+            }
+            AST::MethodCall { name, .. } => name == "select",
+            _ => false,
+        }
+    }
+
+    // Builds the plan of `User.select(...).join(...).where(...).orderBy(...)`,
+    // with its expressions simplified.
+    pub fn build(tree: AST) -> Result<Self, String> {
+        let mut joins = vec![];
+        let mut predicates = vec![];
+        let mut sort = None;
+        let mut tree = tree;
+        while let AST::Chain {
+            receiver,
+            name,
+            args,
+        } = tree
+        {
+            match (&*name, args.as_slice()) {
+                ("where", _) => {
+                    predicates.splice(0..0, args.iter().cloned());
+                }
+                ("orderBy", [key]) => sort = sort.or_else(|| Some((key.clone(), false))),
+                ("orderBy", [key, AST::NamedArg(desc, descending)]) if desc == "desc" => {
+                    let descending = **descending == AST::Bool(true);
+                    sort = sort.or_else(|| Some((key.clone(), descending)));
+                }
+                ("join", [AST::Ident(table), on]) => {
+                    joins.push((table.clone(), optimize::fold(on.clone())))
+                }
+                _ => return Err("Unknown method!!".to_string()),
+            };
+            tree = *receiver;
+        }
+        let (table, exprs) = match tree {
+            AST::MethodCall { table, name, args } if name == "select" => (table, args),
+            _ => return Err("Not a query!!".to_string()),
+        };
+        let exprs: Vec<AST> = exprs.into_iter().map(Self::fold_column).collect();
+        let predicates = optimize::predicates(predicates);
+        let mut plan = Logical::Scan(table);
+        for (table, on) in joins.into_iter().rev() {
+            plan = Logical::Join {
+                left: Box::new(plan),
+                right: Box::new(Logical::Scan(table)),
+                on,
+            };
+        }
+        if !predicates.is_empty() {
+            plan = Logical::Filter {
+                input: Box::new(plan),
+                predicates,
+            };
+        }
+        if let Some((key, descending)) = sort {
+            plan = Logical::Sort {
+                input: Box::new(plan),
+                key: optimize::fold(key),
+                descending,
+            };
+        }
+        if exprs.iter().any(|expr| aggregated(expr).is_some()) {
+            plan = Logical::Aggregate {
+                input: Box::new(plan),
+                exprs,
+            };
+        } else if !exprs.is_empty() {
+            plan = Logical::Project {
+                input: Box::new(plan),
+                exprs,
+            };
+        }
+        Ok(plan)
+    }
+
+    // Folds a selected expression, labelled with its source text so the
+    // column keeps its name.
+    fn fold_column(expr: AST) -> AST {
+        if let AST::NamedArg(..) = expr {
+            return optimize::fold(expr);
+        }
+        let folded = optimize::fold(expr.clone());
+        if folded == expr {
+            return expr;
+        }
+        AST::named_arg(expr.to_string(), folded)
+    }
+}
+
+// How a logical plan runs against the tables it reads.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Physical {
+    SeqScan(String),
+    // Rows whose `field` equals `key`, found through a hash index.
+    IndexScan {
+        table: String,
+        field: String,
+        key: AST,
+    },
+    NestedLoopJoin {
+        left: Box<Physical>,
+        right: Box<Physical>,
+        on: AST,
+    },
+    // Rows of `table` whose `field` equals `key` of each outer row, found
+    // through a hash index. Swapped joins put `table`'s columns first.
+    IndexJoin {
+        outer: Box<Physical>,
+        table: String,
+        field: String,
+        key: AST,
+        on: AST,
+        swapped: bool,
+    },
+    Filter {
+        input: Box<Physical>,
+        predicates: Vec<AST>,
+    },
+    Sort {
+        input: Box<Physical>,
+        key: AST,
+        descending: bool,
+    },
+    Project {
+        input: Box<Physical>,
+        exprs: Vec<AST>,
+    },
+    Aggregate {
+        input: Box<Physical>,
+        exprs: Vec<AST>,
+    },
+}
+
+impl Physical {
+    // Picks the cheapest access path for each table and join by the
+    // statistics `analyze` gathered.
+    pub fn plan(logical: Logical, db: &Database) -> Self {
+        match logical {
+            Logical::Scan(table) => Physical::SeqScan(table),
+            Logical::Filter { input, predicates } => match *input {
+                Logical::Scan(table) => {
+                    let mut candidates = vec![Self::filter(
+                        Physical::SeqScan(table.clone()),
+                        predicates.clone(),
+                    )];
+                    for (i, predicate) in predicates.iter().enumerate() {
+                        if let Some((field, key)) = index_key(predicate, &table, db) {
+                            let mut rest = predicates.clone();
+                            rest.remove(i);
+                            let table = table.clone();
+                            let scan = Physical::IndexScan { table, field, key };
+                            candidates.push(Self::filter(scan, rest));
+                        }
+                    }
+                    Self::cheapest(candidates, db)
+                }
+                input => Self::filter(Self::plan(input, db), predicates),
+            },
+            Logical::Join { left, right, on } => {
+                let written = Logical::Join { left, right, on };
+                let mut candidates = vec![Self::join(written.clone(), db)];
+                for (order, columns) in reorderings(&written, db) {
+                    candidates.push(Physical::Project {
+                        input: Box::new(Self::join(order, db)),
+                        exprs: columns,
+                    });
+                }
+                Self::cheapest(candidates, db)
+            }
+            Logical::Sort {
+                input,
+                key,
+                descending,
+            } => Physical::Sort {
+                input: Box::new(Self::plan(*input, db)),
+                key,
+                descending,
+            },
+            Logical::Project { input, exprs } => Physical::Project {
+                input: Box::new(Self::plan(*input, db)),
+                exprs,
+            },
+            Logical::Aggregate { input, exprs } => Physical::Aggregate {
+                input: Box::new(Self::plan(*input, db)),
+                exprs,
+            },
+        }
+    }
+
+    // Plans joins in the order given, picking for each the cheapest of an
+    // index join either way round or a nested loop.
+    fn join(logical: Logical, db: &Database) -> Self {
+        let (left, right, on) = match logical {
+            Logical::Join { left, right, on } => (left, right, on),
+            Logical::Filter { input, predicates } => {
+                return Self::filter(Self::join(*input, db), predicates)
+            }
+            logical => return Self::plan(logical, db),
+        };
+        let outer = Self::join((*left).clone(), db);
+        let mut candidates = vec![];
+        if let Logical::Scan(table) = &*right {
+            if let Some((field, key)) = join_key(&on, table, &left, db) {
+                candidates.push(Physical::IndexJoin {
+                    outer: Box::new(outer.clone()),
+                    table: table.clone(),
+                    field,
+                    key,
+                    on: on.clone(),
+                    swapped: false,
+                });
+            }
+        }
+        if let Logical::Scan(table) = &*left {
+            if let Some((field, key)) = join_key(&on, table, &right, db) {
+                candidates.push(Physical::IndexJoin {
+                    outer: Box::new(Self::plan((*right).clone(), db)),
+                    table: table.clone(),
+                    field,
+                    key,
+                    on: on.clone(),
+                    swapped: true,
+                });
+            }
+        }
+        candidates.push(Physical::NestedLoopJoin {
+            left: Box::new(outer),
+            right: Box::new(Self::plan(*right, db)),
+            on,
+        });
+        Self::cheapest(candidates, db)
+    }
+
+    fn filter(input: Self, predicates: Vec<AST>) -> Self {
+        if predicates.is_empty() {
+            return input;
+        }
+        Physical::Filter {
+            input: Box::new(input),
+            predicates,
+        }
+    }
+
+    // The first of the cheapest `candidates`.
+    fn cheapest(candidates: Vec<Self>, db: &Database) -> Self {
+        let mut cheapest: Option<(f64, Self)> = None;
+        for candidate in candidates {
+            let cost = candidate.estimate(db).cost;
+            if cheapest.as_ref().is_none_or(|(least, _)| cost < *least) {
+                cheapest = Some((cost, candidate));
+            }
+        }
+        cheapest.expect("No candidate plans!!").1
+    }
+
+    pub fn estimate(&self, db: &Database) -> Estimate {
+        match self {
+            Physical::SeqScan(table) => {
+                let rows = table_rows(table, db);
+                Estimate { cost: rows, rows }
+            }
+            Physical::IndexScan { table, field, .. } => {
+                let rows =
+                    table_rows(table, db) * Statistics::equality(db.statistics(table), field);
+                Estimate {
+                    cost: 1.0 + rows * RANDOM_ACCESS,
+                    rows,
+                }
+            }
+            Physical::NestedLoopJoin { left, right, on } => {
+                let (left, right) = (left.estimate(db), right.estimate(db));
+                Estimate {
+                    cost: left.cost + right.cost + left.rows * right.rows,
+                    rows: left.rows * right.rows * join_selectivity(on, db),
+                }
+            }
+            Physical::IndexJoin {
+                outer,
+                table,
+                field,
+                ..
+            } => {
+                let outer = outer.estimate(db);
+                let matches =
+                    table_rows(table, db) * Statistics::equality(db.statistics(table), field);
+                Estimate {
+                    cost: outer.cost + outer.rows * (1.0 + matches * RANDOM_ACCESS),
+                    rows: outer.rows * matches,
+                }
+            }
+            Physical::Filter { input, predicates } => {
+                let estimate = input.estimate(db);
+                let statistics = match &**input {
+                    Physical::SeqScan(table) | Physical::IndexScan { table, .. } => {
+                        db.statistics(table)
+                    }
+                    _ => None,
+                };
+                let selectivity: f64 = predicates
+                    .iter()
+                    .map(|predicate| Statistics::selectivity(statistics, predicate))
+                    .product();
+                Estimate {
+                    cost: estimate.cost + estimate.rows,
+                    rows: estimate.rows * selectivity,
+                }
+            }
+            Physical::Sort { input, .. } => {
+                let estimate = input.estimate(db);
+                Estimate {
+                    cost: estimate.cost + estimate.rows * estimate.rows.max(2.0).log2(),
+                    rows: estimate.rows,
+                }
+            }
+            Physical::Project { input, .. } => {
+                let estimate = input.estimate(db);
+                Estimate {
+                    cost: estimate.cost + estimate.rows,
+                    rows: estimate.rows,
+                }
+            }
+            Physical::Aggregate { input, .. } => {
+                let estimate = input.estimate(db);
+                Estimate {
+                    cost: estimate.cost + estimate.rows,
+                    rows: 1.0,
+                }
+            }
+        }
+    }
+
+    pub fn execute(&self, db: &Database, version: Version) -> Result<Rows, String> {
+        match self {
+            Physical::SeqScan(table) => {
+                let table = db.relation(table, version)?;
+                Ok((table.members().to_vec(), table.select(&[], version)?))
+            }
+            Physical::IndexScan { table, field, key } => {
+                let table = db.relation(table, version)?;
+                let key = Value::eval_row(key, &[], &[])?;
+                let rows = table
+                    .lookup(field, &key, version)?
+                    .into_iter()
+                    .map(|position| table.values(position).clone())
+                    .collect();
+                Ok((table.members().to_vec(), rows))
+            }
+            Physical::NestedLoopJoin { left, right, on } => {
+                let (mut members, outer) = left.execute(db, version)?;
+                let (inner_members, inner) = right.execute(db, version)?;
+                qualify(left, &mut members);
+                members.extend(inner_members);
+                qualify(right, &mut members);
+                let on = Program::compile(on, &members);
+                let mut rows = vec![];
+                for l in &outer {
+                    for r in &inner {
+                        let row: Values = l.iter().chain(r).cloned().collect();
+                        if on.run(&row)? == Value::Bool(true) {
+                            rows.push(row);
+                        }
+                    }
+                }
+                Ok((members, rows))
+            }
+            Physical::IndexJoin {
+                outer,
+                table,
+                field,
+                key,
+                on,
+                swapped,
+            } => {
+                let (mut outer_members, outer_rows) = outer.execute(db, version)?;
+                qualify(outer, &mut outer_members);
+                let inner = db.relation(table, version)?;
+                let mut inner_members = inner.members().to_vec();
+                qualify(&Physical::SeqScan(table.clone()), &mut inner_members);
+                let members = match swapped {
+                    true => [inner_members, outer_members.clone()].concat(),
+                    false => [outer_members.clone(), inner_members].concat(),
+                };
+                let key = Program::compile(key, &outer_members);
+                let on = Program::compile(on, &members);
+                let mut rows = vec![];
+                for o in &outer_rows {
+                    let key = key.run(o)?;
+                    for position in inner.lookup(field, &key, version)? {
+                        let i = inner.values(position);
+                        let row = match swapped {
+                            true => [i.as_slice(), o].concat(),
+                            false => [o.as_slice(), i].concat(),
+                        };
+                        if on.run(&row)? == Value::Bool(true) {
+                            rows.push(row);
+                        }
+                    }
+                }
+                Ok((members, rows))
+            }
+            Physical::Filter { input, predicates } => {
+                let (members, rows) = input.execute(db, version)?;
+                let predicates = Program::compile_all(predicates, &members);
+                let mut filtered = vec![];
+                for row in rows {
+                    if Program::all(&predicates, &row)? {
+                        filtered.push(row);
+                    }
+                }
+                Ok((members, filtered))
+            }
+            Physical::Sort {
+                input,
+                key,
+                descending,
+            } => {
+                let (members, rows) = input.execute(db, version)?;
+                let key = Program::compile(key, &members);
+                let mut keyed = vec![];
+                for row in rows {
+                    keyed.push((key.run(&row)?, row));
+                }
+                keyed.sort_by(|(a, _), (b, _)| if *descending { b.cmp(a) } else { a.cmp(b) });
+                Ok((members, keyed.into_iter().map(|(_, row)| row).collect()))
+            }
+            Physical::Project { input, exprs } => {
+                let (members, rows) = input.execute(db, version)?;
+                project(&members, exprs, &rows)
+            }
+            Physical::Aggregate { input, exprs } => {
+                let (members, rows) = input.execute(db, version)?;
+                aggregate(&members, exprs, &rows)
+            }
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{}", "  ".repeat(depth))?;
+        let exprs = |exprs: &[AST]| {
+            let exprs: Vec<String> = exprs.iter().map(|expr| expr.to_string()).collect();
+            exprs.join(", ")
+        };
+        match self {
+            Physical::SeqScan(table) => return writeln!(f, "SeqScan {}", table),
+            Physical::IndexScan { table, field, key } => {
+                return writeln!(f, "IndexScan {} ({} == {})", table, field, key)
+            }
+            Physical::NestedLoopJoin { left, right, on } => {
+                writeln!(f, "NestedLoopJoin ({})", on)?;
+                left.fmt_indented(f, depth + 1)?;
+                return right.fmt_indented(f, depth + 1);
+            }
+            Physical::IndexJoin {
+                outer,
+                table,
+                field,
+                on,
+                ..
+            } => {
+                writeln!(f, "IndexJoin {}.{} ({})", table, field, on)?;
+                return outer.fmt_indented(f, depth + 1);
+            }
+            Physical::Filter { predicates, .. } => writeln!(f, "Filter ({})", exprs(predicates))?,
+            Physical::Sort {
+                key, descending, ..
+            } => writeln!(f, "Sort {}{}", key, if *descending { " desc" } else { "" })?,
+            Physical::Project { exprs: e, .. } => writeln!(f, "Project {}", exprs(e))?,
+            Physical::Aggregate { exprs: e, .. } => writeln!(f, "Aggregate {}", exprs(e))?,
+        }
+        match self {
+            Physical::Filter { input, .. }
+            | Physical::Sort { input, .. }
+            | Physical::Project { input, .. }
+            | Physical::Aggregate { input, .. } => input.fmt_indented(f, depth + 1),
+            _ => Ok(()),
+        }
+    }
+}
+
+// One node per line, children indented below their parent.
+impl fmt::Display for Physical {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+// `field == constant` on an indexed column of `table`, as field and key.
+fn index_key(predicate: &AST, table: &str, db: &Database) -> Option<(String, AST)> {
+    if let AST::BinOP(left, OP::EqEq, right) = predicate {
+        let (field, key) = match (&**left, &**right) {
+            (AST::Ident(field), key) | (key, AST::Ident(field)) => (field, key),
+            _ => return None,
+        };
+        if db.indexed(table, field) && Value::eval_row(key, &[], &[]).is_ok() {
+            return Some((field.clone(), key.clone()));
+        }
+    }
+    None
+}
+
+// `table.field == key` in a join condition, where `field` is indexed and
+// `key` is a column of the other side of the join.
+fn join_key(on: &AST, table: &str, other: &Logical, db: &Database) -> Option<(String, AST)> {
+    if let AST::BinOP(left, OP::EqEq, right) = on {
+        let has = |table: &str, field: &str| {
+            db.schema(table)
+                .is_some_and(|members| members.iter().any(|m| m.field == field))
+        };
+        let others = scanned(other);
+        let of = |expr: &AST, table: &str| match expr {
+            AST::Field(t, field) if t == table && has(t, field) => Some(field.clone()),
+            AST::Ident(field) if has(table, field) => Some(field.clone()),
+            _ => None,
+        };
+        for (column, key) in [(&**left, &**right), (&**right, &**left)] {
+            let field = match of(column, table) {
+                Some(field) => field,
+                None => continue,
+            };
+            let outer = others.iter().any(|other| of(key, other).is_some());
+            let ambiguous = others.iter().any(|other| of(column, other).is_some());
+            if db.indexed(table, &field) && outer && !ambiguous {
+                return Some((field, key.clone()));
+            }
+        }
+    }
+    None
+}
+
+// The same joins in every other order of the tables, each with the columns
+// as the written order puts them. A condition joins as soon as the tables
+// it reads are in; one more ready at once filters the joined rows.
+fn reorderings(written: &Logical, db: &Database) -> Vec<(Logical, Vec<AST>)> {
+    let mut conditions = vec![];
+    let mut join = written;
+    while let Logical::Join { left, right, on } = join {
+        match &**right {
+            Logical::Scan(_) => conditions.push(on.clone()),
+            _ => return vec![],
+        }
+        join = left;
+    }
+    let tables = scanned(written);
+    let mut unique = tables.clone();
+    unique.sort();
+    unique.dedup();
+    if tables.len() > MAX_REORDERED
+        || unique.len() < tables.len()
+        || tables.iter().any(|table| db.schema(table).is_none())
+    {
+        return vec![];
+    }
+    let reads: Vec<Vec<&String>> = conditions
+        .iter()
+        .map(|on| tables.iter().filter(|table| reads(on, table, db)).collect())
+        .collect();
+    let columns: Vec<AST> = tables
+        .iter()
+        .flat_map(|table| {
+            db.schema(table)
+                .unwrap_or(&[])
+                .iter()
+                .map(move |m| AST::Ident(format!("{}.{}", table, m.field)))
+        })
+        .collect();
+    let mut orders = vec![];
+    for order in permutations(&tables).into_iter().skip(1) {
+        let mut plan = Logical::Scan(order[0].clone());
+        let mut joined = vec![&order[0]];
+        let mut pending: Vec<usize> = (0..conditions.len()).collect();
+        let mut rest = vec![];
+        for table in &order[1..] {
+            joined.push(table);
+            let (ready, waiting): (Vec<usize>, Vec<usize>) = pending
+                .into_iter()
+                .partition(|&i| reads[i].iter().all(|table| joined.contains(table)));
+            pending = waiting;
+            let mut ready = ready.into_iter().map(|i| conditions[i].clone());
+            plan = Logical::Join {
+                left: Box::new(plan),
+                right: Box::new(Logical::Scan(table.clone())),
+                on: ready.next().unwrap_or(AST::Bool(true)),
+            };
+            rest.extend(ready);
+        }
+        if !rest.is_empty() {
+            plan = Logical::Filter {
+                input: Box::new(plan),
+                predicates: rest,
+            };
+        }
+        orders.push((plan, columns.clone()));
+    }
+    orders
+}
+
+// Whether join condition `on` reads a column of `table`, named or not.
+fn reads(on: &AST, table: &str, db: &Database) -> bool {
+    let has = |field: &str| {
+        db.schema(table)
+            .is_some_and(|members| members.iter().any(|m| m.field == field))
+    };
+    on.clone()
+        .try_map(&mut |node| match &node {
+            AST::Field(t, _) if t == table => Err(String::new()),
+            AST::Ident(field) if has(field) => Err(String::new()),
+            _ => Ok(node),
+        })
+        .is_err()
+}
+
+// Every order of `items`, the given one first.
+fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+    if items.len() <= 1 {
+        return vec![items.to_vec()];
+    }
+    let mut orders = vec![];
+    for i in 0..items.len() {
+        let mut rest = items.to_vec();
+        let first = rest.remove(i);
+        for mut order in permutations(&rest) {
+            order.insert(0, first.clone());
+            orders.push(order);
+        }
+    }
+    orders
+}
+
+// The tables a logical plan reads.
+fn scanned(logical: &Logical) -> Vec<String> {
+    match logical {
+        Logical::Scan(table) => vec![table.clone()],
+        Logical::Join { left, right, .. } => [scanned(left), scanned(right)].concat(),
+        Logical::Filter { input, .. }
+        | Logical::Sort { input, .. }
+        | Logical::Project { input, .. }
+        | Logical::Aggregate { input, .. } => scanned(input),
+    }
+}
+
+fn table_rows(table: &str, db: &Database) -> f64 {
+    db.statistics(table)
+        .map_or(DEFAULT_ROWS, |statistics| statistics.rows as f64)
+}
+
+// The fraction of row pairs a join keeps: one in the distinct values of the
+// joined column with the most of them.
+fn join_selectivity(on: &AST, db: &Database) -> f64 {
+    let column = |expr: &AST| match expr {
+        AST::Field(table, field) => Some(Statistics::equality(db.statistics(table), field)),
+        _ => None,
+    };
+    match on {
+        AST::BinOP(left, OP::EqEq, right) => match (column(left), column(right)) {
+            (Some(l), Some(r)) => l.min(r),
+            (Some(s), None) | (None, Some(s)) => s,
+            (None, None) => Statistics::equality(None, ""),
+        },
+        // A cross join keeps every pair.
+        AST::Bool(true) => 1.0,
+        _ => Statistics::selectivity(None, on),
+    }
+}
+
+// Prefixes the columns a scan produced with its table, once they sit next
+// to another table's columns in a join.
+fn qualify(plan: &Physical, members: &mut [Member]) {
+    let table = match plan {
+        Physical::SeqScan(table) | Physical::IndexScan { table, .. } => table,
+        Physical::Filter { input, .. } => return qualify(input, members),
+        _ => return,
+    };
+    for member in members.iter_mut().filter(|m| !m.field.contains('.')) {
+        member.field = format!("{}.{}", table, member.field);
+    }
+}
+
+// Evaluates the expressions of `select(id, upper(name))` on every row.
+// Computed columns are named by their `name: expr` label or source text and
+// typed after the expression, or else their first non-null value.
+pub fn project(members: &[Member], exprs: &[AST], rows: &[Values]) -> Result<Rows, String> {
+    let programs: Vec<Program> = exprs
+        .iter()
+        .map(|expr| match expr {
+            AST::NamedArg(_, expr) => Program::compile(expr, members),
+            expr => Program::compile(expr, members),
+        })
+        .collect();
+    let mut projected = vec![];
+    for row in rows {
+        let mut values = vec![];
+        for program in &programs {
+            values.push(program.run(row)?);
+        }
+        projected.push(values);
+    }
+    Ok((columns(members, exprs, &projected), projected))
+}
+
+fn columns(members: &[Member], exprs: &[AST], rows: &[Values]) -> Vec<Member> {
+    let mut columns = vec![];
+    for (i, expr) in exprs.iter().enumerate() {
+        if let AST::Ident(field) = expr {
+            if let Some(member) = members.iter().find(|m| m.field == *field) {
+                columns.push(member.clone());
+                continue;
+            }
+        }
+        let field = match expr {
+            AST::NamedArg(field, _) => field.clone(),
+            expr => expr.to_string(),
+        };
+        let first = rows.iter().map(|row| &row[i]).find(|v| **v != Value::Null);
+        let typ = typ(expr, members).unwrap_or(match first {
+            Some(Value::Int(_)) => Type::Int,
+            Some(Value::Bool(_)) => Type::Bool,
+            _ => Type::StrLiteral,
+        });
+        columns.push(Member::new(&field, typ));
+    }
+    columns
+}
+
+// The type of the values `expr` gives, where the expression tells.
+fn typ(expr: &AST, members: &[Member]) -> Option<Type> {
+    match expr {
+        AST::NamedArg(_, expr) => typ(expr, members),
+        AST::Ident(field) => column(members, field).ok().map(|i| members[i].typ.clone()),
+        AST::Field(table, field) => qualified(members, table, field)
+            .ok()
+            .map(|i| members[i].typ.clone()),
+        AST::Number(_) => Some(Type::Int),
+        AST::StrLiteral(_) => Some(Type::StrLiteral),
+        AST::Bool(_) => Some(Type::Bool),
+        AST::BinOP(_, OP::Add | OP::Minus | OP::Mul | OP::Div, _) => Some(Type::Int),
+        AST::BinOP(..) => Some(Type::Bool),
+        AST::Call(name, args) => {
+            let args: Vec<Option<Type>> = args.iter().map(|arg| typ(arg, members)).collect();
+            function::returns(name, &args)
+        }
+        _ => None,
+    }
+}
+
+// The aggregate call `expr` makes, if any, as in `total: sum(age)`.
+fn aggregated(expr: &AST) -> Option<(&str, &[AST])> {
+    match expr {
+        AST::NamedArg(_, expr) => aggregated(expr),
+        AST::Call(name, args) if function::is_aggregate(name) => Some((name, args)),
+        _ => None,
+    }
+}
+
+// Folds all rows into one, which needs every expression to be an aggregate.
+fn aggregate(members: &[Member], exprs: &[AST], rows: &[Values]) -> Result<Rows, String> {
+    let mut values = vec![];
+    for expr in exprs {
+        let (name, args) = aggregated(expr)
+            .ok_or_else(|| "Can't select columns next to aggregates.".to_string())?;
+        let args = Program::compile_all(args, members);
+        let mut calls = vec![];
+        for row in rows {
+            let args = args
+                .iter()
+                .map(|arg| arg.run(row))
+                .collect::<Result<Values, _>>()?;
+            calls.push(args);
+        }
+        values.push(function::aggregate(name, &calls)?);
+    }
+    let rows = vec![values];
+    Ok((columns(members, exprs, &rows), rows))
+}
+
+#[cfg(test)]
+use crate::parser::run;
+
+#[test]
+fn build() {
+    let tree =
+        run("User.select(name).join(Order, userId == id).where(age > 3).orderBy(age, desc: true)");
+    let scan = |table: &str| Box::new(Logical::Scan(table.to_string()));
+    let ident = |name: &str| AST::Ident(name.to_string());
+    assert_eq!(
+        Logical::build(tree),
+        Ok(Logical::Project {
+            input: Box::new(Logical::Sort {
+                input: Box::new(Logical::Filter {
+                    input: Box::new(Logical::Join {
+                        left: scan("User"),
+                        right: scan("Order"),
+                        on: AST::binop(ident("userId"), OP::EqEq, ident("id")),
+                    }),
+                    predicates: vec![AST::binop(ident("age"), OP::Gt, AST::Number(3))],
+                }),
+                key: ident("age"),
+                descending: true,
+            }),
+            exprs: vec![ident("name")],
+        })
+    );
+    assert!(!Logical::selects(&run("User.describe()")));
+    assert_eq!(
+        Logical::build(run("User.select().limit(3)")),
+        Err("Unknown method!!".to_string())
+    );
+}
+
+#[test]
+fn plan() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, age: int}")).unwrap();
+    db.execute(run("User.createIndex(id, hash)")).unwrap();
+    let physical = |src| Physical::plan(Logical::build(run(src)).unwrap(), &db);
+    assert_eq!(
+        physical("User.select().where(age > 3, 3 == id)").to_string(),
+        "Filter (age > 3)\n  IndexScan User (id == 3)\n"
+    );
+    assert_eq!(
+        physical("User.select().where(age == 3)").to_string(),
+        "Filter (age == 3)\n  SeqScan User\n"
+    );
+    assert_eq!(
+        physical("User.select(id).where(id == age).orderBy(age)").to_string(),
+        "Project id\n  Sort age\n    Filter (id == age)\n      SeqScan User\n"
+    );
+    // Expressions are simplified, selected ones keeping their column name.
+    assert_eq!(
+        physical("User.select(id + 2 * 3, n: 1 + 1).where(3 == id, 2 > 1)").to_string(),
+        "Project id + (2 * 3): id + 6, n: 2\n  IndexScan User (id == 3)\n"
+    );
+    assert_eq!(
+        physical("User.select(1 + 2).where(age < 2 == false)").to_string(),
+        "Project 1 + 2: 3\n  Filter (age >= 2)\n    SeqScan User\n"
+    );
+}
+
+#[test]
+fn cost() {
+    let mut db = Database::new();
+    db.execute(run("Table User {id: int, age: int}")).unwrap();
+    db.execute(run("Table Order {id: int, userId: int}"))
+        .unwrap();
+    db.execute(run("User.createIndex(age, hash)")).unwrap();
+    db.execute(run("User.createIndex(id, hash)")).unwrap();
+    for i in 0..30 {
+        db.execute(AST::MethodCall {
+            table: "User".to_string(),
+            name: "insert".to_string(),
+            args: vec![AST::Number(i), AST::Number(i % 2)],
+        })
+        .unwrap();
+    }
+    db.execute(run("Order.insert(1, 3)")).unwrap();
+    let physical = |db: &Database, src| Physical::plan(Logical::build(run(src)).unwrap(), db);
+
+    // Unanalyzed tables are assumed big, with selective indexes.
+    let join = "User.select().join(Order, userId == User.id)";
+    assert_eq!(
+        physical(&db, "User.select().where(age == 1)").to_string(),
+        "IndexScan User (age == 1)\n"
+    );
+    assert_eq!(
+        physical(&db, join).to_string(),
+        "IndexJoin User.id (userId == User.id)\n  SeqScan Order\n"
+    );
+
+    // Half of the rows match `age == 1`, so scanning is cheaper.
+    db.execute(run("analyze")).unwrap();
+    assert_eq!(
+        physical(&db, "User.select().where(age == 1)").to_string(),
+        "Filter (age == 1)\n  SeqScan User\n"
+    );
+    assert_eq!(
+        physical(&db, "User.select().where(age == 1, id == 4)").to_string(),
+        "Filter (age == 1)\n  IndexScan User (id == 4)\n"
+    );
+    let plan = physical(&db, join);
+    assert_eq!(plan.estimate(&db).rows, 1.0);
+    assert_eq!(
+        plan.execute(&db, 1000)
+            .map(|(members, rows)| (members.len(), rows)),
+        Ok((
+            4,
+            vec![vec![
+                Value::Int(3),
+                Value::Int(1),
+                Value::Int(1),
+                Value::Int(3)
+            ]]
+        ))
+    );
+}
+
+#[test]
+fn projection() {
+    let members = vec![
+        Member::new("id", Type::Int),
+        Member::new("name", Type::StrLiteral),
+    ];
+    let rows = vec![
+        vec![Value::Int(1), Value::StrLiteral("kuru".to_string())],
+        vec![Value::Int(2), Value::Null],
+    ];
+    let name = || AST::Ident("name".to_string());
+    let exprs = vec![
+        AST::Ident("id".to_string()),
+        AST::named_arg(
+            "size".to_string(),
+            AST::Call("len".to_string(), vec![name()]),
+        ),
+        AST::Call(
+            "coalesce".to_string(),
+            vec![name(), AST::StrLiteral("?".to_string())],
+        ),
+    ];
+    let (columns, rows) = project(&members, &exprs, &rows).unwrap();
+    let fields: Vec<&str> = columns.iter().map(|m| &*m.field).collect();
+    assert_eq!(fields, vec!["id", "size", "coalesce(name, \"?\")"]);
+    assert_eq!(columns[1].typ, Type::Int);
+    assert_eq!(
+        rows,
+        vec![
+            vec![
+                Value::Int(1),
+                Value::Int(4),
+                Value::StrLiteral("kuru".to_string())
+            ],
+            vec![
+                Value::Int(2),
+                Value::Null,
+                Value::StrLiteral("?".to_string())
+            ],
+        ]
+    );
+
+    // Types follow the expressions, even with no rows or only nulls.
+    let types = |src: &str, rows: &[Values]| {
+        let exprs = match run(src) {
+            AST::MethodCall { args, .. } => args,
+            _ => unreachable!(),
+        };
+        let (columns, _) = project(&members, &exprs, rows).unwrap();
+        columns
+            .into_iter()
+            .map(|m| m.typ.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let src = "T.select(id + 1, name == \"kuru\", upper(name), 3, coalesce(null, name), id > 1)";
+    assert_eq!(types(src, &[]), "int, bool, string, int, string, bool");
+    let nulls = vec![vec![Value::Null, Value::Null]];
+    assert_eq!(types(src, &nulls), "int, bool, string, int, string, bool");
+    assert_eq!(types("T.select(null)", &[]), "string");
+}
+
+#[test]
+fn join_order() {
+    let mut db = Database::new();
+    db.execute(run("Table Big {id: int}")).unwrap();
+    db.execute(run("Table Mid {id: int, bigId: int}")).unwrap();
+    db.execute(run("Table Small {midId: int}")).unwrap();
+    db.execute(run("Big.createIndex(id, hash)")).unwrap();
+    db.execute(run("Mid.createIndex(id, hash)")).unwrap();
+    for i in 0..100 {
+        db.execute(AST::MethodCall {
+            table: "Big".to_string(),
+            name: "insert".to_string(),
+            args: vec![AST::Number(i)],
+        })
+        .unwrap();
+    }
+    for i in 0..50 {
+        db.execute(AST::MethodCall {
+            table: "Mid".to_string(),
+            name: "insert".to_string(),
+            args: vec![AST::Number(i), AST::Number(i * 2)],
+        })
+        .unwrap();
+    }
+    db.execute(run("Small.insert(7)")).unwrap();
+    db.execute(run("analyze")).unwrap();
+
+    // Written from the biggest table, but planned from the smallest one.
+    let src = "Big.select().join(Mid, Mid.bigId == Big.id).join(Small, Small.midId == Mid.id)";
+    let plan = Physical::plan(Logical::build(run(src)).unwrap(), &db);
+    assert_eq!(
+        plan.to_string(),
+        "Project Big.id, Mid.id, Mid.bigId, Small.midId\n  IndexJoin Big.id (Mid.bigId == Big.id)\n    IndexJoin Mid.id (Small.midId == Mid.id)\n      SeqScan Small\n"
+    );
+    // Columns still come in the written order.
+    let (members, rows) = plan.execute(&db, 1000).unwrap();
+    let fields: Vec<&str> = members.iter().map(|m| &*m.field).collect();
+    assert_eq!(fields, vec!["Big.id", "Mid.id", "Mid.bigId", "Small.midId"]);
+    assert_eq!(
+        rows,
+        vec![vec![
+            Value::Int(14),
+            Value::Int(7),
+            Value::Int(14),
+            Value::Int(7)
+        ]]
+    );
+}
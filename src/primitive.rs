@@ -1,18 +1,20 @@
-use crate::token::{KeywordKind, Token};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Type {
     Int,
     StrLiteral,
+    // Only computed columns, such as `select(age > 3)`, are bool.
+    Bool,
 }
 
-impl Type {
-    pub fn from_token(t: Token) -> Self {
-        match t {
-            Token::Keyword(KeywordKind::Int) => Type::Int,
-            Token::Keyword(KeywordKind::StrLiteral) => Type::StrLiteral,
-            _ => unimplemented!(),
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::StrLiteral => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
         }
     }
 }
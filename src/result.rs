@@ -0,0 +1,154 @@
+use crate::ast::Member;
+use crate::primitive::Type;
+use crate::table::{column, Value, Values};
+use std::convert::TryFrom;
+
+// The rows a query returned, with the name and type of each column.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResultSet {
+    columns: Vec<Member>,
+    rows: Vec<Values>,
+}
+
+impl ResultSet {
+    pub fn new(columns: Vec<Member>, rows: Vec<Values>) -> Self {
+        Self { columns, rows }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| &*c.field).collect()
+    }
+
+    pub fn types(&self) -> Vec<&Type> {
+        self.columns.iter().map(|c| &c.typ).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Row<'_>> {
+        self.rows.iter().map(move |values| Row {
+            columns: &self.columns,
+            values,
+        })
+    }
+
+    pub fn into_rows(self) -> Vec<Values> {
+        self.rows
+    }
+}
+
+impl<'a> IntoIterator for &'a ResultSet {
+    type Item = Row<'a>;
+    type IntoIter = Box<dyn Iterator<Item = Row<'a>> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+// One row of a `ResultSet`, whose columns are read by name.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Row<'a> {
+    columns: &'a [Member],
+    values: &'a [Value],
+}
+
+impl<'a> Row<'a> {
+    // The value of column `name` as a `T`, e.g. `row.get::<i32>("id")`.
+    // Joined columns can be named `User.id` or, if unambiguous, `id`.
+    pub fn get<T>(&self, name: &str) -> Result<T, String>
+    where
+        T: TryFrom<Value, Error = String>,
+    {
+        T::try_from(self.values[column(self.columns, name)?].clone())
+    }
+
+    pub fn values(&self) -> &'a [Value] {
+        self.values
+    }
+}
+
+macro_rules! try_from_value {
+    ($typ: ty, $variant: ident, $name: expr) => {
+        impl TryFrom<Value> for $typ {
+            type Error = String;
+
+            fn try_from(value: Value) -> Result<Self, String> {
+                match value {
+                    Value::$variant(v) => Ok(v),
+                    value => Err(format!("Cannot convert `{}` to {}.", value, $name)),
+                }
+            }
+        }
+
+        // Null is `None`.
+        impl TryFrom<Value> for Option<$typ> {
+            type Error = String;
+
+            fn try_from(value: Value) -> Result<Self, String> {
+                match value {
+                    Value::Null => Ok(None),
+                    value => <$typ>::try_from(value).map(Some),
+                }
+            }
+        }
+    };
+}
+
+try_from_value!(i32, Int, "int");
+try_from_value!(String, StrLiteral, "string");
+try_from_value!(bool, Bool, "bool");
+
+#[test]
+fn result_set() {
+    let columns = vec![
+        Member::new("User.id", Type::Int),
+        Member::new("Order.id", Type::Int),
+        Member::new("name", Type::StrLiteral),
+    ];
+    let rows = vec![
+        vec![
+            Value::Int(1),
+            Value::Int(10),
+            Value::StrLiteral("kuru".to_string()),
+        ],
+        vec![Value::Int(2), Value::Int(20), Value::Null],
+    ];
+    let result = ResultSet::new(columns, rows.clone());
+    assert_eq!(result.names(), vec!["User.id", "Order.id", "name"]);
+    assert_eq!(
+        result.types(),
+        vec![&Type::Int, &Type::Int, &Type::StrLiteral]
+    );
+    assert_eq!(result.len(), 2);
+
+    let first = result.iter().next().unwrap();
+    assert_eq!(first.get::<i32>("User.id"), Ok(1));
+    assert_eq!(first.get::<String>("name"), Ok("kuru".to_string()));
+    assert_eq!(
+        first.get::<i32>("id"),
+        Err("Ambiguous column `id`.".to_string())
+    );
+    assert_eq!(
+        first.get::<bool>("name"),
+        Err("Cannot convert `kuru` to bool.".to_string())
+    );
+    assert_eq!(first.get::<i32>("age"), Err("Unknown column.".to_string()));
+
+    let names: Vec<Option<String>> = (&result)
+        .into_iter()
+        .map(|row| row.get("name").unwrap())
+        .collect();
+    assert_eq!(names, vec![Some("kuru".to_string()), None]);
+    assert_eq!(
+        result.iter().nth(1).unwrap().get::<String>("name"),
+        Err("Cannot convert `null` to string.".to_string())
+    );
+    assert_eq!(result.into_rows(), rows);
+}
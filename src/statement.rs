@@ -0,0 +1,230 @@
+use crate::ast::AST;
+use crate::database::Database;
+use crate::primitive::Type;
+use crate::result::ResultSet;
+use crate::table::{Table, Value};
+use std::collections::BTreeMap;
+
+// A statement parsed once by `Database::prepare` and run any number of times
+// with values bound to its `$1` or `:name` placeholders. Bound values are
+// never lexed as source, so they can't change what the statement does.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    tree: AST,
+    // Every placeholder, with the type of the column it stands for if known.
+    params: BTreeMap<String, Option<Type>>,
+    bound: BTreeMap<String, Value>,
+}
+
+impl Statement {
+    // Finds the placeholders of `tree` and the types they must be bound to:
+    // `User.insert($1, $2)` takes the column types in order, and
+    // `where(id == :id)` or `update(name: :name)` the type of that column.
+    pub fn new(tree: AST, db: &Database) -> Result<Self, String> {
+        let members = root(&tree)
+            .and_then(|table| db.schema(table))
+            .unwrap_or(&[]);
+        let typ = |field: &str| members.iter().find(|m| m.field == field).map(|m| &m.typ);
+        let mut params = BTreeMap::new();
+        let mut note = |param: &str, typ: Option<&Type>| {
+            let noted = params.entry(param.to_string()).or_insert(None);
+            match (&*noted, typ) {
+                (Some(noted), Some(typ)) if noted != typ => Err(format!(
+                    "Parameter `{}` is used as both {} and {}!!",
+                    param, noted, typ
+                )),
+                (_, Some(typ)) => {
+                    *noted = Some(typ.clone());
+                    Ok(())
+                }
+                (_, None) => Ok(()),
+            }
+        };
+        tree.clone().try_map(&mut |node| {
+            match &node {
+                AST::Param(param) => note(param, None)?,
+                AST::BinOP(left, _, right) => match (&**left, &**right) {
+                    (AST::Ident(field), AST::Param(param))
+                    | (AST::Param(param), AST::Ident(field)) => note(param, typ(field))?,
+                    _ => {}
+                },
+                AST::NamedArg(field, value) => {
+                    if let AST::Param(param) = &**value {
+                        note(param, typ(field))?;
+                    }
+                }
+                AST::MethodCall { name, args, .. } if name == "insert" => {
+                    // Auto columns are skipped when left out, as `Table::generate` does.
+                    let auto = members.iter().filter(|m| m.is_auto()).count();
+                    let skip_auto = auto > 0 && args.len() + auto == members.len();
+                    let members = members.iter().filter(|m| !(skip_auto && m.is_auto()));
+                    for (member, arg) in members.zip(args) {
+                        if let AST::Param(param) = arg {
+                            note(param, Some(&member.typ))?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            Ok(node)
+        })?;
+        Ok(Self {
+            tree,
+            params,
+            bound: BTreeMap::new(),
+        })
+    }
+
+    pub fn params(&self) -> Vec<&str> {
+        self.params.keys().map(|param| &**param).collect()
+    }
+
+    pub fn bind(&mut self, param: &str, value: impl Into<Value>) -> Result<&mut Self, String> {
+        let value = value.into();
+        match self.params.get(param) {
+            None => Err(format!("Unknown parameter `{}`!!", param)),
+            Some(Some(typ)) if !Table::has_type(typ, &value) => {
+                Err(format!("Unmatched type of arg to `{}`!!", param))
+            }
+            Some(_) => {
+                self.bound.insert(param.to_string(), value);
+                Ok(self)
+            }
+        }
+    }
+
+    pub fn execute(&self, db: &mut Database) -> Result<(), String> {
+        db.execute(self.bound()?)
+    }
+
+    pub fn query(&self, db: &Database) -> Result<ResultSet, String> {
+        db.query(self.bound()?)
+    }
+
+    // The statement with each placeholder replaced by its value.
+    fn bound(&self) -> Result<AST, String> {
+        self.tree.clone().try_map(&mut |node| match node {
+            AST::Param(param) => match self.bound.get(&param) {
+                Some(value) => Ok(value.clone().into()),
+                None => Err(format!("Parameter `{}` is not bound!!", param)),
+            },
+            node => Ok(node),
+        })
+    }
+}
+
+// The table a statement starts from.
+fn root(tree: &AST) -> Option<&str> {
+    match tree {
+        AST::MethodCall { table, .. } => Some(table),
+        AST::Chain { receiver, .. } => root(receiver),
+        AST::Explain(query) => root(query),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+use crate::parser::run;
+
+#[test]
+fn prepare() {
+    let mut db = Database::new();
+    db.prepare("Table User {id: int, name: string}")
+        .unwrap()
+        .execute(&mut db)
+        .unwrap();
+
+    let mut insert = db.prepare("User.insert($1, $2)").unwrap();
+    assert_eq!(insert.params(), vec!["$1", "$2"]);
+    for (id, name) in [(1, "kuru"), (2, "\"); drop User; (\""), (3, "knium")] {
+        insert.bind("$1", id).unwrap().bind("$2", name).unwrap();
+        insert.execute(&mut db).unwrap();
+    }
+    assert_eq!(
+        insert.bind("$1", "4").map(|_| ()),
+        Err("Unmatched type of arg to `$1`!!".to_string())
+    );
+    assert_eq!(
+        insert.bind("$3", 4).map(|_| ()),
+        Err("Unknown parameter `$3`!!".to_string())
+    );
+
+    let mut select = db
+        .prepare("User.select(name).where(id >= :low, :low + 1 >= id)")
+        .unwrap();
+    assert_eq!(
+        select.query(&db).map(ResultSet::into_rows),
+        Err("Parameter `:low` is not bound!!".to_string())
+    );
+    let s = |s: &str| Value::StrLiteral(s.to_string());
+    select.bind(":low", 2).unwrap();
+    assert_eq!(
+        select.query(&db).map(ResultSet::into_rows),
+        Ok(vec![vec![s("\"); drop User; (\"")], vec![s("knium")]])
+    );
+    select.bind(":low", Value::Null).unwrap();
+    assert_eq!(select.query(&db).map(ResultSet::into_rows), Ok(vec![]));
+
+    let mut update = db
+        .prepare("User.select().where(id == :id).update(name: :name)")
+        .unwrap();
+    update
+        .bind(":name", "kurumi")
+        .unwrap()
+        .bind(":id", 1)
+        .unwrap();
+    update.execute(&mut db).unwrap();
+    select.bind(":low", 0).unwrap();
+    assert_eq!(
+        select.query(&db).map(ResultSet::into_rows),
+        Ok(vec![vec![s("kurumi")]])
+    );
+    assert_eq!(
+        db.prepare("User.select().where(id == $1, name == $1)")
+            .map(|_| ()),
+        Err("Parameter `$1` is used as both int and string!!".to_string())
+    );
+    let mut explain = db.prepare("explain User.select().where(id == $1)").unwrap();
+    explain.bind("$1", 3).unwrap();
+    assert_eq!(
+        explain.query(&db).map(ResultSet::into_rows),
+        Ok(vec![vec![s("Filter (id == 3)")], vec![s("  SeqScan User")]])
+    );
+
+    // An unbound placeholder can't be planned as an index key.
+    db.prepare("User.createIndex(id, hash)")
+        .unwrap()
+        .execute(&mut db)
+        .unwrap();
+    assert_eq!(
+        db.query(run("User.select().where(id == $1)"))
+            .map(ResultSet::into_rows),
+        Err("Parameter `$1` is not bound!!".to_string())
+    );
+    // Malformed source is an error, not a panic.
+    for (src, msg) in [
+        ("User.select(", "Unexpected end of input!!"),
+        ("User.select().where(id == $)", "Unexpected char '$'!!"),
+        ("User.select().where(id ! 1)", "Unexpected char '!'!!"),
+        ("User.insert(1, \"kuru)", "Unterminated string!!"),
+        ("Table Bad {id: foo}", "Unexpected token Ident(\"foo\")!!"),
+        ("drop 3", "Unexpected token Number(3)!!"),
+    ] {
+        assert_eq!(db.prepare(src).map(|_| ()), Err(msg.to_string()), "{}", src);
+    }
+
+    // Auto columns left out of an insert don't take a placeholder.
+    db.prepare("Table Item {id: int auto, name: string}")
+        .unwrap()
+        .execute(&mut db)
+        .unwrap();
+    let mut insert = db.prepare("Item.insert($1)").unwrap();
+    insert.bind("$1", "kuru").unwrap().execute(&mut db).unwrap();
+    assert_eq!(
+        db.prepare("Item.select()")
+            .unwrap()
+            .query(&db)
+            .map(ResultSet::into_rows),
+        Ok(vec![vec![Value::Int(1), s("kuru")]])
+    );
+}
@@ -0,0 +1,142 @@
+use crate::ast::{Member, AST, OP};
+use crate::table::{Value, Values};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+// Buckets per histogram.
+const BUCKETS: usize = 10;
+// Selectivities assumed when a table was never analyzed.
+const EQUALITY: f64 = 0.1;
+const RANGE: f64 = 1.0 / 3.0;
+const OTHER: f64 = 0.25;
+
+// What `analyze` found in a table, used to estimate what plans cost.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Statistics {
+    pub rows: usize,
+    pub columns: BTreeMap<String, Column>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Column {
+    pub distinct: usize,
+    pub nulls: usize,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    // Upper bounds of buckets holding about the same number of values.
+    pub histogram: Vec<Value>,
+}
+
+impl Statistics {
+    pub fn analyze(members: &[Member], rows: &[Values]) -> Self {
+        let mut columns = BTreeMap::new();
+        for (i, member) in members.iter().enumerate() {
+            let mut values: Vec<&Value> = rows
+                .iter()
+                .map(|row| &row[i])
+                .filter(|value| **value != Value::Null)
+                .collect();
+            values.sort();
+            let distinct = values.iter().collect::<HashSet<_>>().len();
+            let histogram = (1..=BUCKETS)
+                .filter_map(|bucket| values.get((bucket * values.len() / BUCKETS).checked_sub(1)?))
+                .map(|value| (*value).clone())
+                .collect();
+            let column = Column {
+                distinct,
+                nulls: rows.len() - values.len(),
+                min: values.first().map(|value| (*value).clone()),
+                max: values.last().map(|value| (*value).clone()),
+                histogram,
+            };
+            columns.insert(member.field.clone(), column);
+        }
+        Self {
+            rows: rows.len(),
+            columns,
+        }
+    }
+
+    // The fraction of rows with `field` equal to some constant.
+    pub fn equality(statistics: Option<&Self>, field: &str) -> f64 {
+        match statistics.and_then(|s| s.columns.get(field)) {
+            Some(column) => 1.0 / column.distinct.max(1) as f64,
+            None => EQUALITY,
+        }
+    }
+
+    // The fraction of rows satisfying `predicate`.
+    pub fn selectivity(statistics: Option<&Self>, predicate: &AST) -> f64 {
+        let (field, op, key) = match predicate {
+            AST::BinOP(left, op, right) => match (&**left, &**right) {
+                (AST::Ident(field), key) => (field, op.clone(), key),
+                (key, AST::Ident(field)) => (field, op.flip(), key),
+                _ => return OTHER,
+            },
+            _ => return OTHER,
+        };
+        let key = match Value::eval_row(key, &[], &[]) {
+            Ok(key) => key,
+            Err(_) => return OTHER,
+        };
+        let column = statistics.and_then(|s| s.columns.get(field));
+        match (op, column) {
+            (OP::EqEq, _) => Self::equality(statistics, field),
+            (OP::NotEq, _) => 1.0 - Self::equality(statistics, field),
+            (OP::Lt, Some(column)) | (OP::LtEq, Some(column)) => column.below(&key),
+            (OP::Gt, Some(column)) | (OP::GtEq, Some(column)) => 1.0 - column.below(&key),
+            (OP::Lt, None) | (OP::LtEq, None) | (OP::Gt, None) | (OP::GtEq, None) => RANGE,
+            _ => OTHER,
+        }
+    }
+}
+
+impl Column {
+    // The fraction of values below `key`, from the buckets ending below it.
+    fn below(&self, key: &Value) -> f64 {
+        if self.histogram.is_empty() {
+            return RANGE;
+        }
+        let buckets = self.histogram.iter().filter(|bound| *bound < key).count();
+        buckets as f64 / self.histogram.len() as f64
+    }
+}
+
+#[cfg(test)]
+use crate::primitive::Type;
+
+#[test]
+fn analyze() {
+    let members = vec![
+        Member::new("id", Type::Int),
+        Member::new("name", Type::StrLiteral),
+    ];
+    let rows: Vec<Values> = (1..=20)
+        .map(|i| {
+            let name = match i % 4 {
+                0 => Value::Null,
+                n => Value::StrLiteral(n.to_string()),
+            };
+            vec![Value::Int(i), name]
+        })
+        .collect();
+    let statistics = Statistics::analyze(&members, &rows);
+    assert_eq!(statistics.rows, 20);
+    let id = &statistics.columns["id"];
+    assert_eq!(id.distinct, 20);
+    assert_eq!(id.min, Some(Value::Int(1)));
+    assert_eq!(id.max, Some(Value::Int(20)));
+    assert_eq!(id.histogram.len(), 10);
+    assert_eq!(id.histogram[0], Value::Int(2));
+    let name = &statistics.columns["name"];
+    assert_eq!((name.distinct, name.nulls), (3, 5));
+
+    let statistics = Some(&statistics);
+    let id = || AST::Ident("id".to_string());
+    let predicate = |tree| Statistics::selectivity(statistics, &tree);
+    assert_eq!(predicate(AST::binop(id(), OP::EqEq, AST::Number(3))), 0.05);
+    assert_eq!(predicate(AST::binop(AST::Number(5), OP::Gt, id())), 0.2);
+    assert_eq!(predicate(AST::binop(id(), OP::GtEq, AST::Number(11))), 0.5);
+    assert_eq!(Statistics::selectivity(None, &AST::Bool(true)), OTHER);
+    assert_eq!(Statistics::equality(None, "id"), EQUALITY);
+}
@@ -1,44 +1,296 @@
-use crate::ast::{Member, AST, OP};
+use crate::ast::{Constraint, Member, Method, Trigger, AST, OP};
+use crate::csv;
+use crate::function;
+use crate::json;
 use crate::primitive::Type;
+use crate::statistics::Statistics;
+use crate::vm::Program;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
 
 type Identifier = String;
-type Values = Vec<Value>;
+pub type Values = Vec<Value>;
+pub type Version = u64;
 
-// TODO: Rename better and Retype.
-type R = Result<(), &'static str>;
+// Methods handled by `Table::execute` or `Database`, which user methods can't shadow.
+const BUILTIN_METHODS: &[&str] = &[
+    "select",
+    "where",
+    "describe",
+    "insert",
+    "delete",
+    "update",
+    "refresh",
+    "renameTo",
+    "importCsv",
+    "exportCsv",
+    "importNdjson",
+    "exportJson",
+    "exportNdjson",
+    "addColumn",
+    "dropColumn",
+    "renameColumn",
+    "createIndex",
+    "dropTrigger",
+    "dropMethod",
+];
 
-#[derive(Serialize, Deserialize, Debug)]
+// TODO: Rename better.
+type R = Result<(), String>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Table {
     name: Identifier,
     members: Vec<Member>,
-    column: Vec<Values>,
+    column: Vec<Row>,
+    indexes: BTreeMap<Identifier, Index>,
+    checks: Vec<AST>,
+    // Largest value stored so far in each `auto` column.
+    counters: BTreeMap<Identifier, i32>,
+    triggers: Vec<Trigger>,
+    methods: Vec<Method>,
+    // What the last `analyze` found, if the table was ever analyzed.
+    statistics: Option<Statistics>,
+    // Row versions retired since the last vacuum.
+    #[serde(skip)]
+    retired: usize,
+    // Positions retired since the last `mark`.
+    #[serde(skip)]
+    removed: Vec<usize>,
+}
+
+// Where the rows of a table stood when a statement started writing to them,
+// so `undo` can take back just what it wrote.
+#[derive(Debug, Clone)]
+pub struct Mark {
+    rows: usize,
+    counters: BTreeMap<Identifier, i32>,
+    retired: usize,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
-enum Value {
+// One version of a row, visible to readers between `created` and `deleted`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Row {
+    values: Values,
+    created: Version,
+    deleted: Option<Version>,
+}
+
+impl Row {
+    fn visible(&self, version: Version) -> bool {
+        self.created <= version && self.deleted.is_none_or(|deleted| deleted > version)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum IndexKind {
+    Hash,
+}
+
+// Maps a column value to the positions of the rows holding it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Index {
+    kind: IndexKind,
+    position: usize,
+    entries: HashMap<Value, Vec<usize>>,
+}
+
+impl Index {
+    fn new(kind: IndexKind, position: usize, column: &[Row]) -> Self {
+        let mut index = Self {
+            kind,
+            position,
+            entries: HashMap::new(),
+        };
+        for (row, values) in column.iter().enumerate() {
+            index.add(row, &values.values);
+        }
+        index
+    }
+
+    fn add(&mut self, row: usize, values: &Values) {
+        let key = values[self.position].clone();
+        self.entries.entry(key).or_default().push(row);
+    }
+
+    fn get(&self, key: &Value) -> &[usize] {
+        self.entries.get(key).map_or(&[], |rows| rows)
+    }
+}
+
+// Ordered by variant first, so sorting puts null last.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum Value {
     Int(i32),
     StrLiteral(String),
     Bool(bool),
+    // Allowed in any column.
+    Null,
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::StrLiteral(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::StrLiteral(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<Value> for AST {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Int(i) => AST::Number(i),
+            Value::StrLiteral(s) => AST::StrLiteral(s),
+            Value::Bool(b) => AST::Bool(b),
+            Value::Null => AST::Null,
+        }
+    }
 }
 
 impl Value {
-    fn eval_ast(tree: AST) -> Self {
+    // Evaluates `tree` against a row, resolving identifiers as column names.
+    pub fn eval_row(tree: &AST, members: &[Member], row: &[Value]) -> Result<Self, String> {
         match tree {
-            AST::Number(i) => Value::Int(i),
-            AST::StrLiteral(s) => Value::StrLiteral(s),
+            AST::Number(i) => Ok(Value::Int(*i)),
+            AST::StrLiteral(s) => Ok(Value::StrLiteral(s.clone())),
+            AST::Bool(b) => Ok(Value::Bool(*b)),
+            AST::Null => Ok(Value::Null),
+            AST::Ident(field) => Ok(row[column(members, field)?].clone()),
+            AST::Field(table, field) => Ok(row[qualified(members, table, field)?].clone()),
             AST::BinOP(left, op, right) => {
-                let left = Self::eval_ast(*left);
-                let right = Self::eval_ast(*right);
-                match (left, op, right) {
-                    (Value::Int(lhs), OP::Add, Value::Int(rhs)) => Value::Int(lhs + rhs),
-                    (Value::Int(lhs), OP::Minus, Value::Int(rhs)) => Value::Int(lhs - rhs),
-                    (Value::Int(lhs), OP::Mul, Value::Int(rhs)) => Value::Int(lhs * rhs),
-                    (Value::Int(lhs), OP::EqEq, Value::Int(rhs)) => Value::Bool(lhs == rhs),
-                    _ => unimplemented!(),
-                }
+                let left = Self::eval_row(left, members, row)?;
+                let right = Self::eval_row(right, members, row)?;
+                Self::binop(left, op, right)
             }
-            _ => unimplemented!(),
+            AST::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| Self::eval_row(arg, members, row))
+                    .collect::<Result<Values, _>>()?;
+                function::call(name, &args)
+            }
+            AST::NextVal(sequence) => Err(format!("Can't call nextval({}) in a query!!", sequence)),
+            AST::Param(param) => Err(format!("Parameter `{}` is not bound!!", param)),
+            _ => Err("Unimplemented AST!!".to_string()),
+        }
+    }
+
+    pub fn binop(left: Self, op: &OP, right: Self) -> Result<Self, String> {
+        match (left, op, right) {
+            (Value::Int(_), OP::Div, Value::Int(0)) => Err("Division by zero.".to_string()),
+            (Value::Int(lhs), op @ (OP::Add | OP::Minus | OP::Mul | OP::Div), Value::Int(rhs)) => {
+                let result = match op {
+                    OP::Add => lhs.checked_add(rhs),
+                    OP::Minus => lhs.checked_sub(rhs),
+                    OP::Mul => lhs.checked_mul(rhs),
+                    _ => lhs.checked_div(rhs),
+                };
+                result
+                    .map(Value::Int)
+                    .ok_or_else(|| "Integer overflow.".to_string())
+            }
+            (lhs, OP::EqEq, rhs) => Ok(Value::Bool(lhs == rhs)),
+            (lhs, OP::NotEq, rhs) => Ok(Value::Bool(lhs != rhs)),
+            // Any other operation on null is null.
+            (Value::Null, _, _) | (_, _, Value::Null) => Ok(Value::Null),
+            (Value::Int(lhs), op, Value::Int(rhs)) if op.negate().is_some() => {
+                Ok(Value::Bool(compare(op, lhs, rhs)))
+            }
+            (Value::StrLiteral(lhs), op, Value::StrLiteral(rhs)) if op.negate().is_some() => {
+                Ok(Value::Bool(compare(op, lhs, rhs)))
+            }
+            _ => Err("Unmatched type of arg.".to_string()),
+        }
+    }
+
+    // Converts text from an external source into a value of type `typ`.
+    fn parse(typ: &Type, s: &str) -> Result<Self, String> {
+        match typ {
+            Type::Int => match s.trim().parse() {
+                Ok(i) => Ok(Value::Int(i)),
+                Err(_) => Err(format!("expected int, found `{}`", s)),
+            },
+            Type::StrLiteral => Ok(Value::StrLiteral(s.to_string())),
+            Type::Bool => match s.trim() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(format!("expected bool, found `{}`", s)),
+            },
+        }
+    }
+}
+
+// Finds `field` among `members`, which are qualified like `User.id` after a join.
+pub fn column(members: &[Member], field: &str) -> Result<usize, String> {
+    if let Some(index) = members.iter().position(|m| m.field == field) {
+        return Ok(index);
+    }
+    let suffix = format!(".{}", field);
+    let mut matching = members
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.field.ends_with(&suffix));
+    match (matching.next(), matching.next()) {
+        (Some((index, _)), None) => Ok(index),
+        (Some(_), Some(_)) => Err(format!("Ambiguous column `{}`.", field)),
+        (None, _) => Err("Unknown column.".to_string()),
+    }
+}
+
+// Finds `table.field` among `members`, qualified or not.
+pub fn qualified(members: &[Member], table: &str, field: &str) -> Result<usize, String> {
+    let qualified = format!("{}.{}", table, field);
+    members
+        .iter()
+        .position(|m| m.field == qualified || m.field == field)
+        .ok_or_else(|| "Unknown column.".to_string())
+}
+
+// Whether `expr` reads column `field`.
+fn reads(expr: &AST, field: &str) -> bool {
+    expr.clone()
+        .try_map(&mut |node| match node {
+            AST::Ident(ref name) if name == field => Err(String::new()),
+            node => Ok(node),
+        })
+        .is_err()
+}
+
+fn compare<T: PartialOrd>(op: &OP, lhs: T, rhs: T) -> bool {
+    match op {
+        OP::Lt => lhs < rhs,
+        OP::LtEq => lhs <= rhs,
+        OP::Gt => lhs > rhs,
+        OP::GtEq => lhs >= rhs,
+        _ => unreachable!(),
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::StrLiteral(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Null => write!(f, "null"),
         }
     }
 }
@@ -55,48 +307,738 @@ impl Table {
             name,
             members,
             column: vec![],
+            indexes: BTreeMap::new(),
+            checks: vec![],
+            counters: BTreeMap::new(),
+            triggers: vec![],
+            methods: vec![],
+            statistics: None,
+            retired: 0,
+            removed: vec![],
+        }
+    }
+
+    pub fn with_checks(mut self, checks: Vec<AST>) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    // A table holding `rows` from the start, used for system tables.
+    pub fn from_rows(name: Identifier, members: Vec<Member>, rows: Vec<Values>) -> Self {
+        let mut table = Self::new(name, members);
+        for values in rows {
+            table.push(values, 0);
         }
+        table
     }
 
-    fn eval_args(args: Vec<AST>) -> Values {
+    pub fn position(&self, field: &str) -> Option<usize> {
+        self.members.iter().position(|m| m.field == field)
+    }
+
+    fn eval_args(args: Vec<AST>) -> Result<Values, String> {
         let mut column = vec![];
-        for tree in args.into_iter() {
-            column.push(Value::eval_ast(tree));
+        for tree in args.iter() {
+            column.push(Value::eval_row(tree, &[], &[])?);
         }
-        column
+        Ok(column)
     }
 
     fn type_check(&self, args: &Values) -> R {
         if args.len() != self.members.len() {
-            return Err("Wrong number of arguments.");
+            return Err("Wrong number of arguments.".to_string());
         }
         for (index, arg) in args.iter().enumerate() {
             let typ = &self.members.get(index).unwrap().typ;
-            match (typ, arg) {
-                type_value!(Int) | type_value!(StrLiteral) => (),
-                _ => return Err("Unmatched type of arg."),
+            if !Self::has_type(typ, arg) {
+                return Err("Unmatched type of arg.".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Check constraints compiled against the columns, with the error each
+    // fails with.
+    fn checks(&self) -> Vec<(Program, String)> {
+        let mut checks = vec![];
+        for member in &self.members {
+            for expr in member.checks() {
+                let on = format!("{}.{}", self.name, member.field);
+                checks.push(self.check(expr, &on));
+            }
+        }
+        for expr in &self.checks {
+            checks.push(self.check(expr, &self.name));
+        }
+        checks
+    }
+
+    fn check(&self, expr: &AST, on: &str) -> (Program, String) {
+        let msg = format!("Check constraint `{}` on {} failed.", expr, on);
+        (Program::compile(expr, &self.members), msg)
+    }
+
+    // Type checks `row` and evaluates every check constraint against it.
+    fn validate(&self, checks: &[(Program, String)], row: &Values) -> R {
+        self.type_check(row)?;
+        for (program, msg) in checks {
+            if program.run(row)? != Value::Bool(true) {
+                return Err(msg.clone());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn has_type(typ: &Type, value: &Value) -> bool {
+        matches!(
+            (typ, value),
+            type_value!(Int) | type_value!(StrLiteral) | type_value!(Bool) | (_, Value::Null)
+        )
+    }
+
+    // Schema changes rewrite every stored version of a row, so older
+    // snapshots see the new shape too.
+    fn add_column(&mut self, member: Member, default: Option<&AST>) -> R {
+        if self.position(&member.field).is_some() {
+            return Err("Column already exists.".to_string());
+        }
+        let value = match default {
+            Some(tree) => Value::eval_row(tree, &[], &[])?,
+            None => match member.typ {
+                Type::Int => Value::Int(0),
+                Type::StrLiteral => Value::StrLiteral(String::new()),
+                Type::Bool => Value::Bool(false),
+            },
+        };
+        if !Self::has_type(&member.typ, &value) {
+            return Err("Unmatched type of arg.".to_string());
+        }
+        for row in self.column.iter_mut() {
+            row.values.push(value.clone());
+        }
+        self.members.push(member);
+        Ok(())
+    }
+
+    fn drop_column(&mut self, field: &str) -> R {
+        let position = self
+            .position(field)
+            .ok_or_else(|| "Unknown column.".to_string())?;
+        // Checks on the column itself go with it.
+        let used = self
+            .members
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != position)
+            .flat_map(|(_, member)| member.checks())
+            .chain(&self.checks)
+            .find(|check| reads(check, field));
+        if let Some(check) = used {
+            return Err(format!("Column is used by check constraint `{}`.", check));
+        }
+        for row in self.column.iter_mut() {
+            row.values.remove(position);
+        }
+        self.members.remove(position);
+        self.indexes.remove(field);
+        self.counters.remove(field);
+        if let Some(statistics) = self.statistics.as_mut() {
+            statistics.columns.remove(field);
+        }
+        for index in self.indexes.values_mut() {
+            if index.position > position {
+                index.position -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn rename_column(&mut self, from: &str, to: &str) -> R {
+        if self.position(to).is_some() {
+            return Err("Column already exists.".to_string());
+        }
+        let position = self
+            .position(from)
+            .ok_or_else(|| "Unknown column.".to_string())?;
+        self.members[position].field = to.to_string();
+        let checks = self
+            .members
+            .iter_mut()
+            .flat_map(|member| member.constraints.iter_mut())
+            .filter_map(|constraint| match constraint {
+                Constraint::Check(check) => Some(check),
+                _ => None,
+            })
+            .chain(self.checks.iter_mut());
+        for check in checks {
+            *check = check
+                .clone()
+                .try_map(&mut |node| match node {
+                    AST::Ident(field) if field == from => Ok(AST::Ident(to.to_string())),
+                    node => Ok(node),
+                })
+                .unwrap();
+        }
+        if let Some(index) = self.indexes.remove(from) {
+            self.indexes.insert(to.to_string(), index);
+        }
+        if let Some(counter) = self.counters.remove(from) {
+            self.counters.insert(to.to_string(), counter);
+        }
+        if let Some(statistics) = self.statistics.as_mut() {
+            if let Some(column) = statistics.columns.remove(from) {
+                statistics.columns.insert(to.to_string(), column);
+            }
+        }
+        Ok(())
+    }
+
+    fn create_index(&mut self, field: &str, kind: IndexKind) -> R {
+        if self.indexes.contains_key(field) {
+            return Err("Index already exists.".to_string());
+        }
+        let position = self
+            .position(field)
+            .ok_or_else(|| "Unknown column.".to_string())?;
+        let index = Index::new(kind, position, &self.column);
+        self.indexes.insert(field.to_string(), index);
+        Ok(())
+    }
+
+    // Answers `column == constant` through a hash index when one exists.
+    fn index_lookup(&self, filter: &AST) -> Option<Vec<usize>> {
+        if let AST::BinOP(left, OP::EqEq, right) = filter {
+            let (field, key) = match (&**left, &**right) {
+                (AST::Ident(field), key) | (key, AST::Ident(field)) => (field, key),
+                _ => return None,
             };
+            let index = self.indexes.get(field)?;
+            let key = Value::eval_row(key, &[], &[]).ok()?;
+            return Some(index.get(&key).to_vec());
+        }
+        None
+    }
+
+    // Evaluates and type checks `args` as a new row.
+    pub fn row(&self, args: Vec<AST>) -> Result<Values, String> {
+        let args = self.generate(Self::eval_args(args)?);
+        self.validate(&self.checks(), &args)?;
+        Ok(args)
+    }
+
+    // Fills in the `auto` columns when `args` leaves all of them out.
+    fn generate(&self, mut args: Values) -> Values {
+        let auto: Vec<&Member> = self.members.iter().filter(|m| m.is_auto()).collect();
+        if auto.is_empty() || args.len() + auto.len() != self.members.len() {
+            return args;
+        }
+        for (position, member) in self.members.iter().enumerate() {
+            if member.is_auto() {
+                let next = self.counters.get(&member.field).unwrap_or(&0) + 1;
+                args.insert(position, Value::Int(next));
+            }
+        }
+        args
+    }
+
+    pub fn values(&self, position: usize) -> &Values {
+        &self.column[position].values
+    }
+
+    // The row addressed by `delete(index)`.
+    pub fn addressed(&self, args: Vec<AST>, version: Version) -> Option<usize> {
+        match Self::eval_args(args).ok()?.first() {
+            Some(Value::Int(index)) => (0..self.column.len())
+                .filter(|&position| self.column[position].visible(version))
+                .nth(*index as usize + 1),
+            _ => None,
+        }
+    }
+
+    // Positions of the rows visible at `version` which satisfy every filter.
+    pub fn find(&self, filters: &[AST], version: Version) -> Result<Vec<usize>, String> {
+        let candidates = match filters.first().and_then(|f| self.index_lookup(f)) {
+            Some(rows) => rows,
+            None => (0..self.column.len()).collect(),
+        };
+        let filters = Program::compile_all(filters, &self.members);
+        let mut positions = vec![];
+        for position in candidates {
+            let row = &self.column[position];
+            if row.visible(version) && Program::all(&filters, &row.values)? {
+                positions.push(position);
+            }
+        }
+        Ok(positions)
+    }
+
+    // Positions of the visible rows whose `field` equals `key`.
+    pub fn lookup(&self, field: &str, key: &Value, version: Version) -> Result<Vec<usize>, String> {
+        let position = self
+            .position(field)
+            .ok_or_else(|| "Unknown column.".to_string())?;
+        let candidates = match self.indexes.get(field) {
+            Some(index) => index.get(key).to_vec(),
+            None => (0..self.column.len()).collect(),
+        };
+        Ok(candidates
+            .into_iter()
+            .filter(|&p| self.column[p].visible(version) && &self.column[p].values[position] == key)
+            .collect())
+    }
+
+    // The row at `position` after applying `field: value` assignments, which
+    // are evaluated against the old row.
+    pub fn assign(&self, position: usize, assignments: &[AST]) -> Result<Values, String> {
+        let old = &self.column[position].values;
+        let mut values = old.clone();
+        for assignment in assignments {
+            match assignment {
+                AST::NamedArg(field, value) => {
+                    let index = self
+                        .position(field)
+                        .ok_or_else(|| "Unknown column.".to_string())?;
+                    values[index] = Value::eval_row(value, &self.members, old)?;
+                }
+                _ => return Err("Expected `field: value`.".to_string()),
+            }
+        }
+        self.validate(&self.checks(), &values)?;
+        Ok(values)
+    }
+
+    pub fn remove(&mut self, position: usize, version: Version) {
+        self.column[position].deleted = Some(version);
+        self.retired += 1;
+        self.removed.push(position);
+    }
+
+    pub fn mark(&mut self) -> Mark {
+        self.removed.clear();
+        Mark {
+            rows: self.column.len(),
+            counters: self.counters.clone(),
+            retired: self.retired,
+        }
+    }
+
+    // Revives the rows removed and drops those pushed since `mark`, at a
+    // cost in proportion to them.
+    pub fn undo(&mut self, mark: Mark) {
+        for position in self.removed.drain(..) {
+            self.column[position].deleted = None;
+        }
+        while self.column.len() > mark.rows {
+            let row = self.column.pop().unwrap();
+            for index in self.indexes.values_mut() {
+                let key = &row.values[index.position];
+                if let Some(rows) = index.entries.get_mut(key) {
+                    rows.pop();
+                    if rows.is_empty() {
+                        index.entries.remove(key);
+                    }
+                }
+            }
+        }
+        self.counters = mark.counters;
+        self.retired = mark.retired;
+    }
+
+    // Whether retired versions make up a large part of the table, so
+    // vacuuming it costs little per version retired.
+    pub fn needs_vacuum(&self) -> bool {
+        self.retired > 0 && self.retired >= self.column.len() / 2
+    }
+
+    // Whether any version was deleted at or before `horizon`.
+    pub fn reclaimable(&self, horizon: Version) -> bool {
+        self.column
+            .iter()
+            .any(|row| row.deleted.is_some_and(|deleted| deleted <= horizon))
+    }
+
+    // Drops the versions deleted at or before `horizon`, which readers at
+    // `horizon` or later can't see, and rebuilds the indexes over the rest.
+    pub fn vacuum(&mut self, horizon: Version) {
+        self.column
+            .retain(|row| row.deleted.is_none_or(|deleted| deleted > horizon));
+        for index in self.indexes.values_mut() {
+            *index = Index::new(index.kind, index.position, &self.column);
+        }
+        self.retired = 0;
+        self.removed.clear();
+    }
+
+    // Updates write a new version of the row and retire the old one.
+    pub fn replace(&mut self, position: usize, values: Values, version: Version) {
+        self.remove(position, version);
+        self.push(values, version);
+    }
+
+    pub fn push(&mut self, values: Values, version: Version) {
+        for (member, value) in self.members.iter().zip(&values) {
+            if let (true, Value::Int(i)) = (member.is_auto(), value) {
+                let counter = self.counters.entry(member.field.clone()).or_insert(0);
+                *counter = (*counter).max(*i);
+            }
+        }
+        for index in self.indexes.values_mut() {
+            index.add(self.column.len(), &values);
+        }
+        self.column.push(Row {
+            values,
+            created: version,
+            deleted: None,
+        });
+    }
+
+    // The rows `importCsv(path)` or `importNdjson(path)` would add: every
+    // record or, if any of them fails to convert, an error.
+    pub fn imported(&self, method: &str, args: &[AST]) -> Result<Vec<Values>, String> {
+        match (method, args) {
+            ("importCsv", [AST::StrLiteral(path)]) => self.read_csv(path, false),
+            ("importCsv", [AST::StrLiteral(path), AST::NamedArg(name, header)])
+                if name == "header" =>
+            {
+                match **header {
+                    AST::Bool(header) => self.read_csv(path, header),
+                    _ => Err("Unmatched type of arg.".to_string()),
+                }
+            }
+            ("importNdjson", [AST::StrLiteral(path)]) => self.read_ndjson(path),
+            _ => Err("Wrong number of arguments.".to_string()),
+        }
+    }
+
+    fn read_csv(&self, path: &str, header: bool) -> Result<Vec<Values>, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let records = csv::records(&src);
+        let mut lines = records
+            .iter()
+            .map(|(number, line)| (number, &**line))
+            .filter(|(_, line)| !line.trim().is_empty());
+
+        // The member each CSV column is stored into.
+        let mut order: Vec<usize> = (0..self.members.len()).collect();
+        if header {
+            if let Some((number, line)) = lines.next() {
+                order = vec![];
+                for field in csv::split(line) {
+                    let field = field.unwrap_or_default();
+                    match self.position(field.trim()) {
+                        Some(position) if !order.contains(&position) => order.push(position),
+                        _ => return Err(format!("line {}: unknown column `{}`.", number, field)),
+                    }
+                }
+                if let Some(missing) = (0..self.members.len()).find(|p| !order.contains(p)) {
+                    let field = &self.members[missing].field;
+                    return Err(format!("line {}: missing column `{}`.", number, field));
+                }
+            }
+        }
+
+        let checks = self.checks();
+        let mut rows = vec![];
+        let mut errors = vec![];
+        for (number, line) in lines {
+            let fields = csv::split(line);
+            if fields.len() != order.len() {
+                errors.push(format!(
+                    "line {}: expected {} fields, found {}.",
+                    number,
+                    order.len(),
+                    fields.len()
+                ));
+                continue;
+            }
+            let mut values = vec![None; order.len()];
+            for (field, &position) in fields.iter().zip(&order) {
+                let member = &self.members[position];
+                let parsed = match field {
+                    Some(field) => Value::parse(&member.typ, field),
+                    None => Ok(Value::Null),
+                };
+                match parsed {
+                    Ok(value) => values[position] = Some(value),
+                    Err(msg) => errors.push(format!(
+                        "line {}: column `{}`: {}.",
+                        number, member.field, msg
+                    )),
+                }
+            }
+            let values: Values = values.into_iter().flatten().collect();
+            if values.len() == order.len() {
+                if let Err(msg) = self.validate(&checks, &values) {
+                    errors.push(format!("line {}: {}", number, msg));
+                }
+            }
+            rows.push(values);
+        }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(rows)
+    }
+
+    // Writes a header with the member names followed by the visible rows,
+    // with null as an empty field.
+    fn export_csv(&self, path: &str, version: Version) -> R {
+        let mut out = csv::join(&self.fields()) + "\n";
+        for row in self.select(&[], version)? {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|value| match value {
+                    Value::Null => String::new(),
+                    value => csv::escape(&value.to_string()),
+                })
+                .collect();
+            out += &(fields.join(",") + "\n");
+        }
+        fs::write(path, out).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // Like `read_csv`, one object per line.
+    fn read_ndjson(&self, path: &str) -> Result<Vec<Values>, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let checks = self.checks();
+        let mut rows = vec![];
+        let mut errors = vec![];
+        for (i, line) in src.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match json::row(&self.members, line).and_then(|row| {
+                self.validate(&checks, &row)?;
+                Ok(row)
+            }) {
+                Ok(row) => rows.push(row),
+                Err(msg) => errors.push(format!("line {}: {}", i + 1, msg)),
+            }
         }
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        Ok(rows)
+    }
+
+    pub fn rename(&mut self, name: Identifier) {
+        self.name = name;
+    }
+
+    // The `references table(field)` constraints of the members.
+    fn references_mut(&mut self) -> impl Iterator<Item = (&mut String, &mut String)> {
+        self.members
+            .iter_mut()
+            .flat_map(|member| member.constraints.iter_mut())
+            .filter_map(|constraint| match constraint {
+                Constraint::References { table, field, .. } => Some((table, field)),
+                _ => None,
+            })
+    }
+
+    // Follows a referenced table to its new name.
+    pub fn rename_referenced_table(&mut self, from: &str, to: &str) {
+        for (table, _) in self.references_mut() {
+            if table == from {
+                *table = to.to_string();
+            }
+        }
+    }
+
+    // Follows a referenced column of `parent` to its new name.
+    pub fn rename_referenced_column(&mut self, parent: &str, from: &str, to: &str) {
+        for (table, field) in self.references_mut() {
+            if table == parent && field == from {
+                *field = to.to_string();
+            }
+        }
+    }
+
+    pub fn indexed(&self, field: &str) -> bool {
+        self.indexes.contains_key(field)
+    }
+
+    pub fn statistics(&self) -> Option<&Statistics> {
+        self.statistics.as_ref()
+    }
+
+    // Gathers statistics over the rows visible at `version`.
+    pub fn analyze(&mut self, version: Version) -> R {
+        let rows = self.select(&[], version)?;
+        self.statistics = Some(Statistics::analyze(&self.members, &rows));
+        Ok(())
+    }
+
+    pub fn triggers(&self) -> &[Trigger] {
+        &self.triggers
+    }
+
+    pub fn add_trigger(&mut self, trigger: Trigger) -> R {
+        if self.triggers.iter().any(|t| t.name == trigger.name) {
+            return Err("Trigger already exists.".to_string());
+        }
+        self.triggers.push(trigger);
+        Ok(())
+    }
+
+    fn drop_trigger(&mut self, name: &str) -> R {
+        match self.triggers.iter().position(|t| t.name == name) {
+            Some(position) => {
+                self.triggers.remove(position);
+                Ok(())
+            }
+            None => Err("Unknown trigger.".to_string()),
+        }
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|m| m.name == name)
+    }
+
+    pub fn add_method(&mut self, method: Method) -> R {
+        if BUILTIN_METHODS.contains(&&*method.name) || self.method(&method.name).is_some() {
+            return Err("Method already exists.".to_string());
+        }
+        if self.calls(&method.body, &method.name) {
+            return Err("Method can't call itself.".to_string());
+        }
+        self.methods.push(method);
         Ok(())
     }
 
-    pub fn execute(&mut self, name: Identifier, args: Vec<AST>) -> R {
+    // Whether `body` calls method `name`, directly or through other methods.
+    // Methods already added never do, so the search ends.
+    fn calls(&self, body: &AST, name: &str) -> bool {
+        let mut callees = vec![];
+        body.clone()
+            .try_map(&mut |node| {
+                if let AST::MethodCall { name, .. } = &node {
+                    callees.push(name.clone());
+                }
+                Ok(node)
+            })
+            .unwrap();
+        callees.iter().any(|callee| {
+            callee == name
+                || self
+                    .method(callee)
+                    .is_some_and(|method| self.calls(&method.body, name))
+        })
+    }
+
+    fn drop_method(&mut self, name: &str) -> R {
+        match self.methods.iter().position(|m| m.name == name) {
+            Some(position) => {
+                self.methods.remove(position);
+                Ok(())
+            }
+            None => Err("Unknown method.".to_string()),
+        }
+    }
+
+    // The body of method `name` with its parameters replaced by `args`.
+    pub fn call(&self, name: &str, args: Vec<AST>) -> Result<AST, String> {
+        let method = self
+            .method(name)
+            .ok_or_else(|| "Unknown method.".to_string())?;
+        if args.len() != method.params.len() {
+            return Err("Wrong number of arguments.".to_string());
+        }
+        let mut values = vec![];
+        for (param, arg) in method.params.iter().zip(args) {
+            let value = Value::eval_row(&arg, &[], &[])?;
+            if !Self::has_type(&param.typ, &value) {
+                return Err("Unmatched type of arg.".to_string());
+            }
+            values.push(value);
+        }
+        method.body.clone().try_map(&mut |node| match node {
+            AST::Ident(name) => match method.params.iter().position(|p| p.field == name) {
+                Some(position) => Ok(values[position].clone().into()),
+                None => Ok(AST::Ident(name)),
+            },
+            node => Ok(node),
+        })
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    fn fields(&self) -> Vec<String> {
+        self.members.iter().map(|m| m.field.clone()).collect()
+    }
+
+    // Returns the rows visible at `version` which satisfy every filter.
+    pub fn select(&self, filters: &[AST], version: Version) -> Result<Vec<Values>, String> {
+        let positions = self.find(filters, version)?;
+        Ok(positions
+            .into_iter()
+            .map(|position| self.values(position).clone())
+            .collect())
+    }
+
+    // Writes are stamped with `version`; deleted rows stay behind for older readers.
+    pub fn execute(&mut self, name: Identifier, args: Vec<AST>, version: Version) -> R {
         match &*name {
             "insert" => {
-                let args = Self::eval_args(args);
-                self.type_check(&args)?;
-                self.column.push(args);
+                let row = self.row(args)?;
+                self.push(row, version);
                 Ok(())
             }
             "delete" => {
-                if let Some(arg) = Self::eval_args(args).get(0) {
-                    if let Value::Int(index) = arg {
-                        self.column.remove(*index as usize + 1);
-                    }
+                if let Some(position) = self.addressed(args, version) {
+                    self.remove(position, version);
+                }
+                Ok(())
+            }
+            "importCsv" | "importNdjson" => {
+                for values in self.imported(&name, &args)? {
+                    self.push(values, version);
                 }
                 Ok(())
             }
-            _ => unimplemented!(),
+            "exportCsv" => match args.as_slice() {
+                [AST::StrLiteral(path)] => self.export_csv(path, version),
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "exportJson" | "exportNdjson" => match args.as_slice() {
+                [AST::StrLiteral(path)] => {
+                    let rows = self.select(&[], version)?;
+                    json::write(path, &name, &self.members, &rows)
+                }
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "addColumn" => match args.as_slice() {
+                [AST::Column(member, default)] => {
+                    self.add_column(member.clone(), default.as_deref())
+                }
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "dropColumn" => match args.as_slice() {
+                [AST::Ident(field)] => self.drop_column(field),
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "renameColumn" => match args.as_slice() {
+                [AST::Ident(from), AST::Ident(to)] => self.rename_column(from, to),
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "dropMethod" => match args.as_slice() {
+                [AST::Ident(name)] => self.drop_method(name),
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "dropTrigger" => match args.as_slice() {
+                [AST::Ident(name)] => self.drop_trigger(name),
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            "createIndex" => match args.as_slice() {
+                [AST::Ident(field), AST::Ident(kind)] => match &**kind {
+                    "hash" => self.create_index(field, IndexKind::Hash),
+                    _ => Err("Unknown index kind.".to_string()),
+                },
+                _ => Err("Wrong number of arguments.".to_string()),
+            },
+            _ => Err(format!("Unknown method `{}`!!", name)),
         }
     }
 }
@@ -110,65 +1052,465 @@ fn new() {
 #[test]
 fn eval_args() {
     let args = vec![AST::Number(1), AST::Number(2)];
-    assert_eq!(Table::eval_args(args), vec![Value::Int(1), Value::Int(2)]);
+    assert_eq!(
+        Table::eval_args(args),
+        Ok(vec![Value::Int(1), Value::Int(2)])
+    );
 
     let args = vec![AST::Number(1), AST::StrLiteral("kuru".to_string())];
     assert_eq!(
         Table::eval_args(args),
-        vec![Value::Int(1), Value::StrLiteral("kuru".to_string())]
+        Ok(vec![Value::Int(1), Value::StrLiteral("kuru".to_string())])
     );
 }
 
 #[test]
 fn type_check() {
-    let table = Table::new(
-        "NewUser".to_string(),
-        vec![Member {
-            field: "id".to_string(),
-            typ: Type::Int,
-        }],
-    );
+    let table = Table::new("NewUser".to_string(), vec![Member::new("id", Type::Int)]);
 
-    assert_eq!(table.type_check(&vec![]), Err("Wrong number of arguments."));
+    assert_eq!(
+        table.type_check(&vec![]),
+        Err("Wrong number of arguments.".to_string())
+    );
 
     assert_eq!(table.type_check(&vec![Value::Int(10)]), Ok(()));
 
     assert_eq!(
         table.type_check(&vec![Value::StrLiteral("hoge".to_string())]),
-        Err("Unmatched type of arg.")
+        Err("Unmatched type of arg.".to_string())
+    );
+}
+
+#[test]
+fn select() {
+    let mut table = Table::new("NewUser".to_string(), vec![Member::new("id", Type::Int)]);
+    for i in 0..3 {
+        table
+            .execute("insert".to_string(), vec![AST::Number(i)], 1)
+            .unwrap();
+    }
+    let filter = AST::binop(AST::Ident("id".to_string()), OP::EqEq, AST::Number(2));
+
+    assert_eq!(table.select(&[], 1).unwrap().len(), 3);
+    assert_eq!(
+        table.select(std::slice::from_ref(&filter), 1),
+        Ok(vec![vec![Value::Int(2)]])
+    );
+
+    // Rows deleted at version 2 are still visible to readers at version 1.
+    table
+        .execute("delete".to_string(), vec![AST::Number(1)], 2)
+        .unwrap();
+    assert_eq!(
+        table
+            .select(std::slice::from_ref(&filter), 1)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(table.select(&[filter], 2).unwrap().len(), 0);
+    assert_eq!(table.select(&[], 0).unwrap().len(), 0);
+}
+
+#[test]
+fn vacuum() {
+    let mut table = Table::new("NewUser".to_string(), vec![Member::new("id", Type::Int)]);
+    for i in 0..3 {
+        table
+            .execute("insert".to_string(), vec![AST::Number(i)], 1)
+            .unwrap();
+    }
+    let args = vec![AST::Ident("id".to_string()), AST::Ident("hash".to_string())];
+    table.execute("createIndex".to_string(), args, 1).unwrap();
+    table
+        .execute("delete".to_string(), vec![AST::Number(0)], 2)
+        .unwrap();
+    assert!(table.needs_vacuum());
+
+    // Readers at version 1 still see the deleted row.
+    table.vacuum(1);
+    assert_eq!(table.column.len(), 3);
+    table.vacuum(2);
+    assert_eq!(table.column.len(), 2);
+    assert!(!table.needs_vacuum());
+    let filter = AST::binop(AST::Ident("id".to_string()), OP::EqEq, AST::Number(2));
+    assert_eq!(table.index_lookup(&filter), Some(vec![1]));
+    assert_eq!(table.select(&[filter], 2), Ok(vec![vec![Value::Int(2)]]));
+}
+
+#[test]
+fn create_index() {
+    let mut table = Table::new("NewUser".to_string(), vec![Member::new("id", Type::Int)]);
+    for i in 0..3 {
+        table
+            .execute("insert".to_string(), vec![AST::Number(i)], 1)
+            .unwrap();
+    }
+    let args = vec![AST::Ident("id".to_string()), AST::Ident("hash".to_string())];
+    assert_eq!(table.execute("createIndex".to_string(), args, 2), Ok(()));
+    table
+        .execute("insert".to_string(), vec![AST::Number(2)], 2)
+        .unwrap();
+
+    let filter = AST::binop(AST::Number(2), OP::EqEq, AST::Ident("id".to_string()));
+    assert_eq!(table.index_lookup(&filter), Some(vec![2, 3]));
+    assert_eq!(
+        table
+            .select(std::slice::from_ref(&filter), 1)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(table.select(&[filter], 2).unwrap().len(), 2);
+
+    let args = vec![
+        AST::Ident("age".to_string()),
+        AST::Ident("hash".to_string()),
+    ];
+    assert_eq!(
+        table.execute("createIndex".to_string(), args, 2),
+        Err("Unknown column.".to_string())
     );
 }
 
 #[test]
 fn eval_ast() {
     let ast = AST::binop(AST::Number(1), OP::Add, AST::Number(2));
-    assert_eq!(Value::eval_ast(ast), Value::Int(3));
+    assert_eq!(Value::eval_row(&ast, &[], &[]), Ok(Value::Int(3)));
 
     let ast = AST::binop(
         AST::binop(AST::Number(1), OP::Add, AST::Number(2)),
         OP::Add,
         AST::Number(3),
     );
-    assert_eq!(Value::eval_ast(ast), Value::Int(6));
+    assert_eq!(Value::eval_row(&ast, &[], &[]), Ok(Value::Int(6)));
 
     let ast = AST::binop(
         AST::binop(AST::Number(1), OP::Mul, AST::Number(2)),
         OP::Add,
         AST::Number(3),
     );
-    assert_eq!(Value::eval_ast(ast), Value::Int(5));
+    assert_eq!(Value::eval_row(&ast, &[], &[]), Ok(Value::Int(5)));
 
     let ast = AST::binop(
         AST::binop(AST::Number(1), OP::Mul, AST::Number(2)),
         OP::Minus,
         AST::Number(3),
     );
-    assert_eq!(Value::eval_ast(ast), Value::Int(-1));
+    assert_eq!(Value::eval_row(&ast, &[], &[]), Ok(Value::Int(-1)));
 
     let ast = AST::binop(
         AST::binop(AST::Number(1), OP::Mul, AST::Number(2)),
         OP::EqEq,
         AST::Number(2),
     );
-    assert_eq!(Value::eval_ast(ast), Value::Bool(true));
+    assert_eq!(Value::eval_row(&ast, &[], &[]), Ok(Value::Bool(true)));
+
+    let ast = AST::binop(AST::Number(1), OP::Div, AST::Number(0));
+    assert_eq!(
+        Value::eval_row(&ast, &[], &[]),
+        Err("Division by zero.".to_string())
+    );
+}
+
+#[test]
+fn csv() {
+    let mut table = Table::new(
+        "NewUser".to_string(),
+        vec![
+            Member::new("id", Type::Int),
+            Member::new("name", Type::StrLiteral),
+        ],
+    );
+    let dir = std::env::temp_dir();
+    let src = dir.join("statikk_import.csv");
+    let src = src.to_str().unwrap();
+    let out = dir.join("statikk_export.csv");
+    let out = out.to_str().unwrap();
+    let import = |header| {
+        vec![
+            AST::StrLiteral(src.to_string()),
+            AST::named_arg("header".to_string(), AST::Bool(header)),
+        ]
+    };
+
+    fs::write(src, "name,id\n\"Knium, Jr.\",1\nkuru,2\n,3\n\"\",4\n").unwrap();
+    assert_eq!(
+        table.execute("importCsv".to_string(), import(true), 1),
+        Ok(())
+    );
+    assert_eq!(
+        table.select(&[], 1).unwrap()[0],
+        vec![Value::Int(1), Value::StrLiteral("Knium, Jr.".to_string())]
+    );
+
+    let args = vec![AST::StrLiteral(out.to_string())];
+    assert_eq!(table.execute("exportCsv".to_string(), args, 1), Ok(()));
+    // Null is an empty field, apart from the empty string.
+    assert_eq!(
+        fs::read_to_string(out).unwrap(),
+        "id,name\n1,\"Knium, Jr.\"\n2,kuru\n3,\n4,\"\"\n"
+    );
+    assert_eq!(
+        table.select(&[], 1).unwrap()[2..],
+        [
+            vec![Value::Int(3), Value::Null],
+            vec![Value::Int(4), Value::StrLiteral(String::new())]
+        ]
+    );
+
+    // Nothing is imported when any record fails to convert.
+    fs::write(src, "3,ok\nfour,bad\n5\n").unwrap();
+    assert_eq!(
+        table.execute("importCsv".to_string(), import(false), 2),
+        Err(
+            "line 2: column `id`: expected int, found `four`.\nline 3: expected 2 fields, found 1."
+                .to_string()
+        )
+    );
+    assert_eq!(table.select(&[], 2).unwrap().len(), 4);
+
+    fs::write(src, "id,age\n").unwrap();
+    assert_eq!(
+        table.execute("importCsv".to_string(), import(true), 2),
+        Err("line 1: unknown column `age`.".to_string())
+    );
+
+    // Quoted newlines and quotes survive a round trip, and errors name the
+    // line their record starts on.
+    let mut copy = Table::new("Copy".to_string(), table.members.clone());
+    let mut multiline = Table::new("Multiline".to_string(), table.members.clone());
+    multiline.push(vec![Value::Int(1), Value::from("a\nb")], 1);
+    multiline.push(vec![Value::Int(2), Value::from("say \"hi\"\n")], 1);
+    let args = vec![AST::StrLiteral(out.to_string())];
+    assert_eq!(multiline.execute("exportCsv".to_string(), args, 1), Ok(()));
+    let args = vec![
+        AST::StrLiteral(out.to_string()),
+        AST::named_arg("header".to_string(), AST::Bool(true)),
+    ];
+    assert_eq!(copy.execute("importCsv".to_string(), args, 1), Ok(()));
+    assert_eq!(copy.select(&[], 1), multiline.select(&[], 1));
+
+    fs::write(src, "1,\"a\nb\"\nfour,x\n").unwrap();
+    assert_eq!(
+        copy.execute("importCsv".to_string(), import(false), 2),
+        Err("line 3: column `id`: expected int, found `four`.".to_string())
+    );
+}
+
+#[test]
+fn ndjson() {
+    let mut table = Table::new("NewUser".to_string(), vec![Member::new("id", Type::Int)]);
+    let path = std::env::temp_dir().join("statikk_import.ndjson");
+    let path = path.to_str().unwrap();
+    let args = || vec![AST::StrLiteral(path.to_string())];
+
+    fs::write(path, "{\"id\": 1}\n\n{\"id\": 2}\n").unwrap();
+    assert_eq!(table.execute("importNdjson".to_string(), args(), 1), Ok(()));
+    assert_eq!(table.select(&[], 1).unwrap().len(), 2);
+
+    fs::write(path, "{\"id\": 3}\n{\"id\": \"four\"}\n").unwrap();
+    assert_eq!(
+        table.execute("importNdjson".to_string(), args(), 2),
+        Err("line 2: Unmatched type of arg.".to_string())
+    );
+    assert_eq!(table.select(&[], 2).unwrap().len(), 2);
+
+    assert_eq!(table.execute("exportNdjson".to_string(), args(), 2), Ok(()));
+    assert_eq!(
+        fs::read_to_string(path).unwrap(),
+        "{\"id\":1}\n{\"id\":2}\n"
+    );
+}
+
+#[test]
+fn alter_column() {
+    let mut table = Table::new(
+        "NewUser".to_string(),
+        vec![
+            Member::new("id", Type::Int),
+            Member::new("name", Type::StrLiteral),
+        ],
+    );
+    let args = vec![AST::Number(1), AST::StrLiteral("kuru".to_string())];
+    table.execute("insert".to_string(), args, 1).unwrap();
+    let args = vec![
+        AST::Ident("name".to_string()),
+        AST::Ident("hash".to_string()),
+    ];
+    table.execute("createIndex".to_string(), args, 1).unwrap();
+    let age = Member::new("age", Type::Int);
+
+    let args = vec![AST::Column(age.clone(), Some(Box::new(AST::Number(20))))];
+    assert_eq!(table.execute("addColumn".to_string(), args, 2), Ok(()));
+    let args = vec![AST::Column(age.clone(), None)];
+    assert_eq!(
+        table.execute("addColumn".to_string(), args, 2),
+        Err("Column already exists.".to_string())
+    );
+    let args = vec![
+        AST::Number(2),
+        AST::StrLiteral("knium".to_string()),
+        AST::Number(30),
+    ];
+    table.execute("insert".to_string(), args, 2).unwrap();
+    assert_eq!(
+        table.select(&[], 2).unwrap()[0],
+        vec![
+            Value::Int(1),
+            Value::StrLiteral("kuru".to_string()),
+            Value::Int(20)
+        ]
+    );
+
+    let args = vec![AST::Ident("id".to_string())];
+    assert_eq!(table.execute("dropColumn".to_string(), args, 3), Ok(()));
+    let args = vec![
+        AST::Ident("name".to_string()),
+        AST::Ident("nick".to_string()),
+    ];
+    assert_eq!(table.execute("renameColumn".to_string(), args, 3), Ok(()));
+
+    // The index followed its column through both changes.
+    let filter = AST::binop(
+        AST::Ident("nick".to_string()),
+        OP::EqEq,
+        AST::StrLiteral("knium".to_string()),
+    );
+    assert_eq!(table.index_lookup(&filter), Some(vec![1]));
+    assert_eq!(
+        table.select(&[filter], 3),
+        Ok(vec![vec![
+            Value::StrLiteral("knium".to_string()),
+            Value::Int(30)
+        ]])
+    );
+    assert_eq!(table.members()[1], age);
+}
+
+#[test]
+fn check_constraint() {
+    let mut age = Member::new("age", Type::Int);
+    let non_negative = AST::binop(AST::Ident("age".to_string()), OP::GtEq, AST::Number(0));
+    age.constraints.push(Constraint::Check(non_negative));
+    let below = AST::binop(
+        AST::Ident("age".to_string()),
+        OP::Lt,
+        AST::Ident("limit".to_string()),
+    );
+    let mut table = Table::new(
+        "NewUser".to_string(),
+        vec![age, Member::new("limit", Type::Int)],
+    )
+    .with_checks(vec![below]);
+
+    let insert = |table: &mut Table, age, limit| {
+        let args = vec![AST::Number(age), AST::Number(limit)];
+        table.execute("insert".to_string(), args, 1)
+    };
+    assert_eq!(insert(&mut table, 3, 10), Ok(()));
+    assert_eq!(
+        insert(&mut table, 0 - 1, 10),
+        Err("Check constraint `age >= 0` on NewUser.age failed.".to_string())
+    );
+    assert_eq!(
+        insert(&mut table, 10, 10),
+        Err("Check constraint `age < limit` on NewUser failed.".to_string())
+    );
+
+    let assignment = AST::named_arg(
+        "age".to_string(),
+        AST::binop(AST::Ident("age".to_string()), OP::Mul, AST::Number(5)),
+    );
+    assert_eq!(
+        table.assign(0, &[assignment]),
+        Err("Check constraint `age < limit` on NewUser failed.".to_string())
+    );
+
+    // Checks follow a renamed column, and keep the columns they read.
+    let args = vec![
+        AST::Ident("age".to_string()),
+        AST::Ident("years".to_string()),
+    ];
+    table.execute("renameColumn".to_string(), args, 1).unwrap();
+    assert_eq!(
+        insert(&mut table, 10, 10),
+        Err("Check constraint `years < limit` on NewUser failed.".to_string())
+    );
+    assert_eq!(
+        table.execute(
+            "dropColumn".to_string(),
+            vec![AST::Ident("limit".to_string())],
+            1
+        ),
+        Err("Column is used by check constraint `years < limit`.".to_string())
+    );
+    table.checks.clear();
+    let args = vec![AST::Ident("years".to_string())];
+    assert_eq!(table.execute("dropColumn".to_string(), args, 1), Ok(()));
+    assert_eq!(
+        table.execute("insert".to_string(), vec![AST::Number(5)], 1),
+        Ok(())
+    );
+}
+
+#[test]
+fn auto() {
+    let mut id = Member::new("id", Type::Int);
+    id.constraints.push(Constraint::Auto);
+    let mut table = Table::new(
+        "NewUser".to_string(),
+        vec![id, Member::new("name", Type::StrLiteral)],
+    );
+    let insert = |table: &mut Table, args| table.execute("insert".to_string(), args, 1);
+    insert(&mut table, vec![AST::StrLiteral("kuru".to_string())]).unwrap();
+    insert(
+        &mut table,
+        vec![AST::Number(10), AST::StrLiteral("knium".to_string())],
+    )
+    .unwrap();
+    insert(&mut table, vec![AST::StrLiteral("statikk".to_string())]).unwrap();
+    let ids: Vec<Value> = table
+        .select(&[], 1)
+        .unwrap()
+        .into_iter()
+        .map(|row| row[0].clone())
+        .collect();
+    assert_eq!(ids, vec![Value::Int(1), Value::Int(10), Value::Int(11)]);
+}
+
+#[test]
+fn call() {
+    let mut table = Table::new(
+        "NewUser".to_string(),
+        vec![Member::new("name", Type::StrLiteral)],
+    );
+    let name = || AST::Ident("name".to_string());
+    let args = vec![AST::Call(
+        "upper".to_string(),
+        vec![AST::StrLiteral("kuru".to_string())],
+    )];
+    table.execute("insert".to_string(), args, 1).unwrap();
+    table
+        .execute("insert".to_string(), vec![AST::Null], 1)
+        .unwrap();
+    assert_eq!(
+        table.select(&[], 1).unwrap(),
+        vec![
+            vec![Value::StrLiteral("KURU".to_string())],
+            vec![Value::Null]
+        ]
+    );
+
+    let filter = AST::binop(
+        AST::Call("len".to_string(), vec![name()]),
+        OP::EqEq,
+        AST::Number(4),
+    );
+    assert_eq!(table.select(&[filter], 1).unwrap().len(), 1);
+    let bad = AST::binop(name(), OP::Add, AST::Number(1));
+    assert_eq!(
+        table.select(&[bad], 1),
+        Err("Unmatched type of arg.".to_string())
+    );
 }
@@ -5,7 +5,14 @@ pub enum Token {
     Symbol(char),
     StrLiteral(String),
     Keyword(KeywordKind),
+    // `$1` or `:name`, spelled as in the source.
+    Param(String),
     EqEq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
     Add,
     Minus,
     Mul,
@@ -17,6 +24,22 @@ pub enum KeywordKind {
     Table,
     Int,
     StrLiteral,
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint,
+    Release,
+    True,
+    False,
+    Drop,
+    Tables,
+    Sequence,
+    View,
+    Trigger,
+    Method,
+    Null,
+    Explain,
+    Analyze,
 }
 
 use KeywordKind::*;
@@ -28,6 +51,22 @@ impl Token {
             "Table" => Keyword(Table),
             "int" => Keyword(Int),
             "string" => Keyword(KeywordKind::StrLiteral),
+            "begin" => Keyword(Begin),
+            "commit" => Keyword(Commit),
+            "rollback" => Keyword(Rollback),
+            "savepoint" => Keyword(Savepoint),
+            "release" => Keyword(Release),
+            "true" => Keyword(True),
+            "false" => Keyword(False),
+            "drop" => Keyword(Drop),
+            "tables" => Keyword(Tables),
+            "sequence" => Keyword(Sequence),
+            "View" => Keyword(View),
+            "trigger" => Keyword(KeywordKind::Trigger),
+            "method" => Keyword(KeywordKind::Method),
+            "null" => Keyword(KeywordKind::Null),
+            "explain" => Keyword(KeywordKind::Explain),
+            "analyze" => Keyword(KeywordKind::Analyze),
             _ => Ident(literal),
         }
     }
@@ -17,32 +17,36 @@ impl<'a> Tokenizer<'a> {
         }
     }
 
-    pub fn lex(&mut self) -> Option<Token> {
+    pub fn lex(&mut self) -> Result<Option<Token>, String> {
         self.read_char();
-        match self.ch {
+        let token = match self.ch {
             // Skip blank chars.
-            Some(' ') | Some('\n') => self.lex(),
+            Some(' ') | Some('\n') => return self.lex(),
 
             // Ident or Keyword?
-            Some('a'...'z') | Some('A'...'Z') => {
+            Some('a'..='z') | Some('A'..='Z') => {
                 let mut buffer = String::new();
-                while let Some('a'...'z') | Some('A'...'Z') = self.ch {
+                while let Some('a'..='z') | Some('A'..='Z') | Some('0'..='9') | Some('_') = self.ch
+                {
                     buffer.push(self.ch.unwrap());
                     self.read_char();
                 }
                 self.backtrack();
-                Some(Token::lookup(buffer))
+                Token::lookup(buffer)
             }
 
             // Number
-            Some('0'...'9') => {
+            Some('0'..='9') => {
                 let mut buffer = String::new();
-                while let Some('0'...'9') = self.ch {
+                while let Some('0'..='9') = self.ch {
                     buffer.push(self.ch.unwrap());
                     self.read_char();
                 }
                 self.backtrack();
-                Some(Token::Number(buffer.parse().unwrap()))
+                match buffer.parse() {
+                    Ok(n) => Token::Number(n),
+                    Err(_) => return Err(format!("Number out of range: {}!!", buffer)),
+                }
             }
 
             // StrLiteral
@@ -50,50 +54,111 @@ impl<'a> Tokenizer<'a> {
                 self.read_char();
                 let mut buffer = String::new();
                 while self.ch != Some('"') {
-                    buffer.push(self.ch.unwrap());
+                    match self.ch {
+                        Some(ch) => buffer.push(ch),
+                        None => return Err("Unterminated string!!".to_string()),
+                    }
                     self.read_char();
                 }
-                Some(Token::StrLiteral(buffer))
+                Token::StrLiteral(buffer)
             }
 
             // Eq or EqEq
-            Some('=') => {
+            Some('=') => self.followed_by_eq(Token::EqEq, Token::Symbol('=')),
+
+            // Comparison OP
+            Some('!') => {
                 self.read_char();
-                if let Some('=') = self.ch {
-                    Some(Token::EqEq)
-                } else {
-                    Some(Token::Symbol('='))
+                match self.ch {
+                    Some('=') => Token::NotEq,
+                    _ => return Err(unexpected('!')),
                 }
             }
+            Some('<') => self.followed_by_eq(Token::LtEq, Token::Lt),
+            Some('>') => self.followed_by_eq(Token::GtEq, Token::Gt),
 
             // Arithmetic OP
-            Some('+') => Some(Token::Add),
-            Some('-') => Some(Token::Minus),
-            Some('*') => Some(Token::Mul),
-            Some('/') => Some(Token::Slash),
+            Some('+') => Token::Add,
+            Some('-') => Token::Minus,
+            Some('*') => Token::Mul,
+            Some('/') => Token::Slash,
+
+            // Placeholders of prepared statements, `$1` or `:name`.
+            Some('$') => {
+                let mut buffer = String::from("$");
+                self.read_char();
+                while let Some('0'..='9') = self.ch {
+                    buffer.push(self.ch.unwrap());
+                    self.read_char();
+                }
+                self.backtrack();
+                if buffer.len() == 1 {
+                    return Err(unexpected('$'));
+                }
+                Token::Param(buffer)
+            }
+            Some(':') if self.starts_param() => {
+                let mut buffer = String::from(":");
+                self.read_char();
+                while let Some('a'..='z') | Some('A'..='Z') | Some('0'..='9') | Some('_') = self.ch
+                {
+                    buffer.push(self.ch.unwrap());
+                    self.read_char();
+                }
+                self.backtrack();
+                Token::Param(buffer)
+            }
 
             // Only Symbol?
             Some(ch) => match ch {
-                '(' | ')' | '.' | ';' | ',' | '{' | '}' | ':' => Some(Token::Symbol(ch)),
-                _ => panic!("unexpected char! {:?}", ch),
+                '(' | ')' | '.' | ';' | ',' | '{' | '}' | ':' => Token::Symbol(ch),
+                _ => return Err(unexpected(ch)),
             },
-            _ => None,
-        }
+            None => return Ok(None),
+        };
+        Ok(Some(token))
     }
 
-    pub fn lex_all(&mut self) -> VecDeque<Token> {
+    pub fn lex_all(&mut self) -> Result<VecDeque<Token>, String> {
         let mut result = VecDeque::new();
-        while let Some(token) = self.lex() {
+        while let Some(token) = self.lex()? {
             result.push_back(token);
         }
-        result
+        Ok(result)
+    }
+
+    // `with` when the next char is '=', otherwise `without`.
+    fn followed_by_eq(&mut self, with: Token, without: Token) -> Token {
+        self.read_char();
+        if let Some('=') = self.ch {
+            with
+        } else {
+            self.backtrack();
+            without
+        }
     }
 
-    #[allow(dead_code)]
     fn peek(&self) -> Option<char> {
         self.src.chars().nth(self.index)
     }
 
+    // Whether the ':' just read is followed by a name where an expression is
+    // expected: at the start or after `(`, `,`, `:` or an operator. Anywhere
+    // else it labels or types what comes before, as in `header:true` or `id :int`.
+    fn starts_param(&self) -> bool {
+        let before = self
+            .src
+            .chars()
+            .take(self.index - 1)
+            .filter(|c| !c.is_whitespace())
+            .last();
+        matches!(self.peek(), Some('a'..='z') | Some('A'..='Z'))
+            && matches!(
+                before,
+                None | Some('(' | ',' | ':' | '=' | '<' | '>' | '+' | '-' | '*' | '/')
+            )
+    }
+
     fn read_char(&mut self) {
         self.ch = self.src.chars().nth(self.index);
         self.index += 1;
@@ -105,6 +170,10 @@ impl<'a> Tokenizer<'a> {
     }
 }
 
+fn unexpected(ch: char) -> String {
+    format!("Unexpected char {:?}!!", ch)
+}
+
 #[allow(unused_imports)]
 use crate::token::KeywordKind;
 
@@ -117,48 +186,60 @@ fn new() {
 #[test]
 fn lex() {
     let mut t = Tokenizer::new("hello");
-    assert_eq!(t.lex(), Some(Token::Ident("hello".to_string())));
+    assert_eq!(t.lex().unwrap(), Some(Token::Ident("hello".to_string())));
 
     let mut t = Tokenizer::new("Knium is godlike!");
-    assert_eq!(t.lex(), Some(Token::Ident("Knium".to_string())));
+    assert_eq!(t.lex().unwrap(), Some(Token::Ident("Knium".to_string())));
+
+    let mut t = Tokenizer::new("user_id2");
+    assert_eq!(t.lex().unwrap(), Some(Token::Ident("user_id2".to_string())));
 
     let mut t = Tokenizer::new("42");
-    assert_eq!(t.lex(), Some(Token::Number(42)));
+    assert_eq!(t.lex().unwrap(), Some(Token::Number(42)));
 
     let mut t = Tokenizer::new("+");
-    assert_eq!(t.lex(), Some(Token::Add));
+    assert_eq!(t.lex().unwrap(), Some(Token::Add));
 
     let mut t = Tokenizer::new("Table");
-    assert_eq!(t.lex(), Some(Token::Keyword(KeywordKind::Table)));
+    assert_eq!(t.lex().unwrap(), Some(Token::Keyword(KeywordKind::Table)));
 
     let mut t = Tokenizer::new("int");
-    assert_eq!(t.lex(), Some(Token::Keyword(KeywordKind::Int)));
+    assert_eq!(t.lex().unwrap(), Some(Token::Keyword(KeywordKind::Int)));
 
     let mut t = Tokenizer::new("\"How are you?\"");
-    assert_eq!(t.lex(), Some(Token::StrLiteral("How are you?".to_string())));
+    assert_eq!(
+        t.lex().unwrap(),
+        Some(Token::StrLiteral("How are you?".to_string()))
+    );
 
     let mut t = Tokenizer::new("=");
-    assert_eq!(t.lex(), Some(Token::Symbol('=')));
+    assert_eq!(t.lex().unwrap(), Some(Token::Symbol('=')));
 
     let mut t = Tokenizer::new("==");
-    assert_eq!(t.lex(), Some(Token::EqEq));
+    assert_eq!(t.lex().unwrap(), Some(Token::EqEq));
+
+    let mut t = Tokenizer::new(">=");
+    assert_eq!(t.lex().unwrap(), Some(Token::GtEq));
+
+    let mut t = Tokenizer::new("!=");
+    assert_eq!(t.lex().unwrap(), Some(Token::NotEq));
 
     let mut t = Tokenizer::new("/");
-    assert_eq!(t.lex(), Some(Token::Slash));
+    assert_eq!(t.lex().unwrap(), Some(Token::Slash));
 }
 
 #[test]
 fn lex_all() {
     let mut t = Tokenizer::new("42+15");
     assert_eq!(
-        t.lex_all(),
+        t.lex_all().unwrap(),
         vec![Token::Number(42), Token::Add, Token::Number(15),]
     );
 
     let input = "User.select();";
     let mut t = Tokenizer::new(input);
     assert_eq!(
-        t.lex_all(),
+        t.lex_all().unwrap(),
         vec![
             Token::Ident("User".to_string()),
             Token::Symbol('.'),
@@ -171,7 +252,7 @@ fn lex_all() {
 
     let mut t = Tokenizer::new("42      + 15 \n + 3");
     assert_eq!(
-        t.lex_all(),
+        t.lex_all().unwrap(),
         vec![
             Token::Number(42),
             Token::Add,
@@ -184,7 +265,76 @@ fn lex_all() {
     let input = "2 == 2";
     let mut t = Tokenizer::new(input);
     assert_eq!(
-        t.lex_all(),
+        t.lex_all().unwrap(),
         vec![Token::Number(2), Token::EqEq, Token::Number(2)]
     );
+
+    let mut t = Tokenizer::new("age=0<1");
+    assert_eq!(
+        t.lex_all().unwrap(),
+        vec![
+            Token::Ident("age".to_string()),
+            Token::Symbol('='),
+            Token::Number(0),
+            Token::Lt,
+            Token::Number(1)
+        ]
+    );
+}
+
+#[test]
+fn params() {
+    let mut t = Tokenizer::new("f($12, :name, header:true, x: :y)");
+    let ident = |s: &str| Token::Ident(s.to_string());
+    let param = |s: &str| Token::Param(s.to_string());
+    assert_eq!(
+        t.lex_all().unwrap(),
+        vec![
+            ident("f"),
+            Token::Symbol('('),
+            param("$12"),
+            Token::Symbol(','),
+            param(":name"),
+            Token::Symbol(','),
+            ident("header"),
+            Token::Symbol(':'),
+            Token::Keyword(KeywordKind::True),
+            Token::Symbol(','),
+            ident("x"),
+            Token::Symbol(':'),
+            param(":y"),
+            Token::Symbol(')'),
+        ]
+    );
+
+    assert_eq!(
+        Tokenizer::new("f($)").lex_all(),
+        Err("Unexpected char '$'!!".to_string())
+    );
+
+    // A spaced-out member type is no placeholder.
+    let mut t = Tokenizer::new("Table User {id :int}");
+    assert_eq!(
+        t.lex_all().unwrap(),
+        vec![
+            Token::Keyword(KeywordKind::Table),
+            ident("User"),
+            Token::Symbol('{'),
+            ident("id"),
+            Token::Symbol(':'),
+            Token::Keyword(KeywordKind::Int),
+            Token::Symbol('}'),
+        ]
+    );
+    let mut t = Tokenizer::new(":a == 1 + :b");
+    assert_eq!(
+        t.lex_all().unwrap(),
+        vec![
+            param(":a"),
+            Token::EqEq,
+            Token::Number(1),
+            Token::Add,
+            param(":b")
+        ]
+    );
 }
@@ -0,0 +1,170 @@
+use crate::ast::{Member, AST, OP};
+use crate::function;
+use crate::table::{column, qualified, Value};
+
+// One step of a compiled expression, working on a stack of values.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instruction {
+    Push(Value),
+    // Pushes the column at this position of the row.
+    Load(usize),
+    // Pops the right operand, then the left one, and pushes the result.
+    BinOP(OP),
+    // Pops this many arguments and pushes what the function returns.
+    Call(String, usize),
+    // Stops with an error, for columns which were not found. Evaluating the
+    // expression tree fails on the same rows.
+    Fail(String),
+}
+
+// An expression compiled against the columns of the rows it will run on, so
+// columns are looked up once instead of once per row.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    code: Vec<Instruction>,
+    // The most values on the stack at once.
+    depth: usize,
+}
+
+impl Program {
+    pub fn compile(tree: &AST, members: &[Member]) -> Self {
+        let mut program = Self {
+            code: vec![],
+            depth: 0,
+        };
+        program.emit(tree, members, 0);
+        program
+    }
+
+    pub fn compile_all(trees: &[AST], members: &[Member]) -> Vec<Self> {
+        trees
+            .iter()
+            .map(|tree| Self::compile(tree, members))
+            .collect()
+    }
+
+    // Appends the code of `tree`, which starts with `height` values on the stack.
+    fn emit(&mut self, tree: &AST, members: &[Member], height: usize) {
+        self.depth = self.depth.max(height + 1);
+        let instruction = match tree {
+            AST::Number(i) => Instruction::Push(Value::Int(*i)),
+            AST::StrLiteral(s) => Instruction::Push(Value::StrLiteral(s.clone())),
+            AST::Bool(b) => Instruction::Push(Value::Bool(*b)),
+            AST::Null => Instruction::Push(Value::Null),
+            AST::Ident(field) => match column(members, field) {
+                Ok(position) => Instruction::Load(position),
+                Err(msg) => Instruction::Fail(msg),
+            },
+            AST::Field(table, field) => match qualified(members, table, field) {
+                Ok(position) => Instruction::Load(position),
+                Err(msg) => Instruction::Fail(msg),
+            },
+            AST::BinOP(left, op, right) => {
+                self.emit(left, members, height);
+                self.emit(right, members, height + 1);
+                Instruction::BinOP(op.clone())
+            }
+            AST::Call(name, args) => {
+                for (i, arg) in args.iter().enumerate() {
+                    self.emit(arg, members, height + i);
+                }
+                Instruction::Call(name.clone(), args.len())
+            }
+            AST::NextVal(sequence) => {
+                Instruction::Fail(format!("Can't call nextval({}) in a query!!", sequence))
+            }
+            AST::Param(param) => Instruction::Fail(format!("Parameter `{}` is not bound!!", param)),
+            _ => Instruction::Fail("Unimplemented AST!!".to_string()),
+        };
+        self.code.push(instruction);
+    }
+
+    pub fn run(&self, row: &[Value]) -> Result<Value, String> {
+        let mut stack = Vec::with_capacity(self.depth);
+        for instruction in &self.code {
+            match instruction {
+                Instruction::Push(value) => stack.push(value.clone()),
+                Instruction::Load(position) => stack.push(row[*position].clone()),
+                Instruction::BinOP(op) => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.pop().unwrap();
+                    stack.push(Value::binop(left, op, right)?);
+                }
+                Instruction::Call(name, arity) => {
+                    let args = stack.split_off(stack.len() - arity);
+                    stack.push(function::call(name, &args)?);
+                }
+                Instruction::Fail(msg) => return Err(msg.clone()),
+            }
+        }
+        Ok(stack.pop().unwrap())
+    }
+
+    // Whether the row satisfies every one of `programs`, as `where` does.
+    pub fn all(programs: &[Self], row: &[Value]) -> Result<bool, String> {
+        for program in programs {
+            if program.run(row)? != Value::Bool(true) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+use crate::{parser::run, primitive::Type};
+
+#[test]
+fn compile() {
+    let members = vec![
+        Member::new("User.id", Type::Int),
+        Member::new("name", Type::StrLiteral),
+    ];
+    let src = "T.select(len(name) + id > 3, User.id, missing)";
+    let exprs = match run(src) {
+        AST::MethodCall { args, .. } => args,
+        _ => unreachable!(),
+    };
+    let programs = Program::compile_all(&exprs, &members);
+    assert_eq!(
+        programs[0].code,
+        vec![
+            Instruction::Load(1),
+            Instruction::Call("len".to_string(), 1),
+            Instruction::Load(0),
+            Instruction::BinOP(OP::Add),
+            Instruction::Push(Value::Int(3)),
+            Instruction::BinOP(OP::Gt),
+        ]
+    );
+    assert_eq!(programs[0].depth, 2);
+    assert_eq!(programs[1].code, vec![Instruction::Load(0)]);
+    assert_eq!(
+        programs[2].code,
+        vec![Instruction::Fail("Unknown column.".to_string())]
+    );
+
+    // The same results and errors as evaluating the tree.
+    let src = "T.select(id * 2 - 1, name == \"kuru\", id / 0, upper(name), \
+               concat(name, \"-\", string(id)), name < 3, coalesce(null, id), missing + 1, \
+               name + name, nextval(seq), id == $1, 2147483647 + id)";
+    let exprs = match run(src) {
+        AST::MethodCall { args, .. } => args,
+        _ => unreachable!(),
+    };
+    let rows = vec![
+        vec![Value::Int(2), Value::StrLiteral("kuru".to_string())],
+        vec![Value::Null, Value::Null],
+    ];
+    for expr in &exprs {
+        let program = Program::compile(expr, &members);
+        for row in &rows {
+            assert_eq!(
+                program.run(row),
+                Value::eval_row(expr, &members, row),
+                "{}",
+                expr
+            );
+        }
+    }
+}